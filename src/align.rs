@@ -0,0 +1,151 @@
+//! Key alignment between two layouts.
+//!
+//! Binary operations need to know, for every position in the left operand,
+//! where the same key lives in the right operand. When both sides share an
+//! `index` `Arc` (or have equal indexes) that is the identity and `merge` can
+//! zip the value arrays directly. Otherwise the answer is a permutation that
+//! is expensive to compute (one hash lookup per key) but never changes for a
+//! given pair of layouts, so it is cached keyed by the pair of `Arc` pointers.
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, Weak};
+
+type Index = HashMap<String, usize>;
+
+/// How positions in a left layout map onto a right layout.
+#[derive(Clone)]
+pub(crate) enum Alignment {
+    /// Every key sits at the same position on both sides.
+    Identical,
+    /// For each left position, the right position holding the same key, or
+    /// `None` when the right side does not have it.
+    Gather(Arc<[Option<usize>]>),
+}
+
+/// Number of layout pairs kept before dead entries are evicted.
+const CACHE_CAPACITY: usize = 256;
+
+struct Entry {
+    // The weak references keep both allocations from being reused while the
+    // entry exists, so a pointer match is always the same layout.
+    left: Weak<Index>,
+    right: Weak<Index>,
+    alignment: Alignment,
+}
+
+impl Entry {
+    fn is_live(&self) -> bool {
+        self.left.strong_count() > 0 && self.right.strong_count() > 0
+    }
+}
+
+static CACHE: LazyLock<Mutex<HashMap<(usize, usize), Entry>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Aligns `left` onto `right`, reusing a cached permutation when this pair of
+/// layouts has been aligned before.
+pub(crate) fn align(left: &Arc<Index>, right: &Arc<Index>) -> Alignment {
+    if Arc::ptr_eq(left, right) {
+        return Alignment::Identical;
+    }
+
+    let key = (Arc::as_ptr(left) as usize, Arc::as_ptr(right) as usize);
+    let mut cache = CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(entry) = cache.get(&key) {
+        return entry.alignment.clone();
+    }
+
+    let alignment = compute(left, right);
+    if cache.len() >= CACHE_CAPACITY {
+        cache.retain(|_, entry| entry.is_live());
+        if cache.len() >= CACHE_CAPACITY {
+            cache.clear();
+        }
+    }
+    cache.insert(
+        key,
+        Entry {
+            left: Arc::downgrade(left),
+            right: Arc::downgrade(right),
+            alignment: alignment.clone(),
+        },
+    );
+    alignment
+}
+
+fn compute(left: &Index, right: &Index) -> Alignment {
+    let mut gather = vec![None; left.len()];
+    for (key, &i) in left.iter() {
+        gather[i] = right.get(key).copied();
+    }
+
+    let identical =
+        left.len() == right.len() && gather.iter().enumerate().all(|(i, j)| *j == Some(i));
+    if identical {
+        Alignment::Identical
+    } else {
+        Alignment::Gather(gather.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(keys: &[&str]) -> Arc<Index> {
+        Arc::new(
+            keys.iter()
+                .enumerate()
+                .map(|(i, k)| (k.to_string(), i))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_same_arc_is_identical() {
+        let left = index(&["a", "b"]);
+        assert!(matches!(align(&left, &left), Alignment::Identical));
+    }
+
+    #[test]
+    fn test_equal_layouts_are_identical() {
+        let left = index(&["a", "b"]);
+        let right = index(&["a", "b"]);
+        assert!(matches!(align(&left, &right), Alignment::Identical));
+    }
+
+    #[test]
+    fn test_gather_maps_positions_and_missing_keys() {
+        let left = index(&["a", "b", "c"]);
+        let right = index(&["c", "a", "z"]);
+        let Alignment::Gather(gather) = align(&left, &right) else {
+            panic!("expected a gather alignment");
+        };
+        assert_eq!(&*gather, [Some(1), None, Some(0)]);
+    }
+
+    #[test]
+    fn test_repeated_alignment_is_cached() {
+        let left = index(&["a", "b"]);
+        let right = index(&["b", "a"]);
+        let (Alignment::Gather(first), Alignment::Gather(second)) =
+            (align(&left, &right), align(&left, &right))
+        else {
+            panic!("expected gather alignments");
+        };
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[test]
+    fn test_cache_is_keyed_by_direction() {
+        let left = index(&["a", "b"]);
+        let right = index(&["b"]);
+        let Alignment::Gather(forward) = align(&left, &right) else {
+            panic!("expected a gather alignment");
+        };
+        let Alignment::Gather(backward) = align(&right, &left) else {
+            panic!("expected a gather alignment");
+        };
+        assert_eq!(&*forward, [None, Some(0)]);
+        assert_eq!(&*backward, [Some(1)]);
+    }
+}
@@ -0,0 +1,198 @@
+//! Array interop: the buffer protocol export of `values`, and reading keys
+//! and values out of numpy (or any buffer-exporting) arrays.
+//!
+//! An exported buffer holds its own reference to the values `Arc`, so later
+//! mutation of the RedDict copies the values first (see `Arc::make_mut`) and
+//! never invalidates or changes memory a consumer is looking at.
+use std::os::raw::{c_int, c_void};
+use std::ptr;
+use std::sync::Arc;
+
+use pyo3::{
+    buffer::{PyBuffer, PyUntypedBuffer},
+    exceptions::{PyBufferError, PyValueError},
+    ffi,
+    prelude::*,
+};
+
+use crate::{bitmap::Bitmap, RedDict};
+
+/// What an exported buffer keeps alive until it is released.
+struct Export {
+    values: Arc<Vec<f64>>,
+    shape: isize,
+}
+
+/// Fills `view` with a read-only, one-dimensional `f64` view of `dict`'s
+/// values.
+///
+/// # Safety
+///
+/// `view` must be null or point to a `Py_buffer` the caller owns, as passed
+/// to `__getbuffer__`.
+pub(crate) unsafe fn fill_buffer(
+    view: *mut ffi::Py_buffer,
+    flags: c_int,
+    dict: &Bound<RedDict>,
+) -> PyResult<()> {
+    if view.is_null() {
+        return Err(PyBufferError::new_err("view is null"));
+    }
+    if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+        return Err(PyBufferError::new_err("RedDict values are read-only"));
+    }
+
+    let values = Arc::clone(&dict.borrow().values);
+    let export = Box::into_raw(Box::new(Export {
+        shape: values.len() as isize,
+        values,
+    }));
+    // SAFETY: the caller guarantees `view` is valid; `export` stays alive
+    // (and with it the values and `shape`) until `release_buffer`.
+    unsafe {
+        (*view).obj = dict.clone().into_any().into_ptr();
+        (*view).buf = (*export).values.as_ptr() as *mut c_void;
+        (*view).len = (*export).shape * 8;
+        (*view).readonly = 1;
+        (*view).itemsize = 8;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            c"d".as_ptr() as *mut _
+        } else {
+            ptr::null_mut()
+        };
+        (*view).ndim = 1;
+        (*view).shape = if flags & ffi::PyBUF_ND == ffi::PyBUF_ND {
+            &mut (*export).shape
+        } else {
+            ptr::null_mut()
+        };
+        (*view).strides = if flags & ffi::PyBUF_STRIDES == ffi::PyBUF_STRIDES {
+            &mut (*view).itemsize
+        } else {
+            ptr::null_mut()
+        };
+        (*view).suboffsets = ptr::null_mut();
+        (*view).internal = export.cast();
+    }
+    Ok(())
+}
+
+/// Drops what `fill_buffer` kept alive for `view`.
+///
+/// # Safety
+///
+/// `view` must have been filled by `fill_buffer` and not released before.
+pub(crate) unsafe fn release_buffer(view: *mut ffi::Py_buffer) {
+    // SAFETY: `internal` was set from `Box::into_raw` in `fill_buffer`.
+    unsafe {
+        drop(Box::from_raw((*view).internal.cast::<Export>()));
+        (*view).internal = ptr::null_mut();
+    }
+}
+
+/// Keys from a sequence of strings. Fixed-width numpy string arrays (dtype
+/// `U`) are decoded straight from their buffer; anything else is iterated.
+pub(crate) fn extract_keys(keys: &Bound<PyAny>) -> PyResult<Vec<String>> {
+    if let Ok(buffer) = PyUntypedBuffer::get(keys) {
+        if let Some(width) = ucs4_width(buffer.format().to_bytes()).filter(|&w| w > 0) {
+            if buffer.dimensions() == 1
+                && buffer.is_c_contiguous()
+                && buffer.item_size() == width * 4
+            {
+                // SAFETY: the buffer is C-contiguous and `len_bytes` long,
+                // and stays alive while `buffer` is held.
+                let bytes = unsafe {
+                    std::slice::from_raw_parts(buffer.buf_ptr() as *const u8, buffer.len_bytes())
+                };
+                return decode_ucs4(bytes, width);
+            }
+        }
+    }
+    keys.try_iter()?.map(|key| key?.extract()).collect()
+}
+
+/// The characters per item of a native-endian UCS4 buffer format such as
+/// `"10w"`, the format numpy uses for `U10` arrays.
+fn ucs4_width(format: &[u8]) -> Option<usize> {
+    let format = match format {
+        [b'@' | b'=', rest @ ..] => rest,
+        [b'<', rest @ ..] if cfg!(target_endian = "little") => rest,
+        [b'>' | b'!', rest @ ..] if cfg!(target_endian = "big") => rest,
+        _ => format,
+    };
+    let digits = format.strip_suffix(b"w")?;
+    if digits.is_empty() {
+        return Some(1);
+    }
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Splits fixed-width UCS4 items of `width` (non-zero) characters, dropping
+/// the NUL padding at the end of each.
+fn decode_ucs4(bytes: &[u8], width: usize) -> PyResult<Vec<String>> {
+    bytes
+        .chunks_exact(width * 4)
+        .map(|item| {
+            item.chunks_exact(4)
+                .map(|c| u32::from_ne_bytes(c.try_into().expect("4 bytes")))
+                .take_while(|&c| c != 0)
+                .map(|c| {
+                    char::from_u32(c)
+                        .ok_or_else(|| PyValueError::new_err("key is not valid unicode"))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Values and their validity from a float64 buffer (numpy arrays of other
+/// dtypes are converted first) or any sequence of floats and `None`s.
+pub(crate) fn extract_values(values: &Bound<PyAny>) -> PyResult<(Vec<f64>, Bitmap)> {
+    if let Ok(buffer) = PyBuffer::<f64>::get(values) {
+        let values = buffer.to_vec(values.py())?;
+        let validity = Bitmap::new_valid(values.len());
+        return Ok((values, validity));
+    }
+    if values.hasattr("astype")? {
+        let converted = values.call_method1("astype", ("float64",))?;
+        if let Ok(buffer) = PyBuffer::<f64>::get(&converted) {
+            let values = buffer.to_vec(values.py())?;
+            let validity = Bitmap::new_valid(values.len());
+            return Ok((values, validity));
+        }
+    }
+    let values: Vec<Option<f64>> = values.extract()?;
+    let validity = Bitmap::from_fn(values.len(), |i| values[i].is_some());
+    Ok((
+        values.iter().map(|v| v.unwrap_or(f64::NAN)).collect(),
+        validity,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ucs4_width_parses_numpy_formats() {
+        assert_eq!(ucs4_width(b"10w"), Some(10));
+        assert_eq!(ucs4_width(b"w"), Some(1));
+        assert_eq!(ucs4_width(b"=3w"), Some(3));
+        assert_eq!(ucs4_width(b"d"), None);
+        assert_eq!(ucs4_width(b"3s"), None);
+    }
+
+    #[test]
+    fn test_decode_ucs4_strips_padding() {
+        let mut bytes = Vec::new();
+        for key in ["ab", "é", ""] {
+            let mut chars: Vec<u32> = key.chars().map(u32::from).collect();
+            chars.resize(3, 0);
+            bytes.extend(chars.iter().flat_map(|c| c.to_ne_bytes()));
+        }
+        assert_eq!(decode_ucs4(&bytes, 3).unwrap(), ["ab", "é", ""]);
+        let invalid = 0xD800u32.to_ne_bytes();
+        Python::initialize();
+        assert!(decode_ucs4(&invalid, 1).is_err());
+    }
+}
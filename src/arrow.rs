@@ -0,0 +1,810 @@
+//! Arrow interchange through the Arrow C Data Interface and its PyCapsule
+//! protocol, without depending on pyarrow.
+//!
+//! A RedDict is exported as a record batch with a non-nullable `key: utf8`
+//! column (`large_utf8` when the keys exceed 2 GiB) and a nullable
+//! `value: float64` column. The values buffer is shared, not copied: the
+//! exported array holds a reference to the values `Arc` until the consumer
+//! releases it. Only the keys are copied, into Arrow's offsets-and-data
+//! layout.
+//!
+//! Importing reads `key` and `value` columns (or the only two columns) of
+//! any struct array or stream of them. Keys may be `utf8`, `large_utf8` or
+//! `utf8_view`; values any of `float64`, `float32`, `int64` or `int32`.
+//! Values are copied once, into the new RedDict.
+use std::any::Any;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::ptr;
+use std::sync::Arc;
+
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    ffi,
+    prelude::*,
+    types::{PyCapsule, PyTuple},
+};
+
+use crate::{bitmap::Bitmap, schema::KeySchema, RedDict};
+
+const FLAG_NULLABLE: i64 = 2;
+
+#[repr(C)]
+pub(crate) struct ArrowSchema {
+    format: *const c_char,
+    name: *const c_char,
+    metadata: *const c_char,
+    flags: i64,
+    n_children: i64,
+    children: *mut *mut ArrowSchema,
+    dictionary: *mut ArrowSchema,
+    release: Option<unsafe extern "C" fn(*mut ArrowSchema)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub(crate) struct ArrowArray {
+    length: i64,
+    null_count: i64,
+    offset: i64,
+    n_buffers: i64,
+    n_children: i64,
+    buffers: *mut *const c_void,
+    children: *mut *mut ArrowArray,
+    dictionary: *mut ArrowArray,
+    release: Option<unsafe extern "C" fn(*mut ArrowArray)>,
+    private_data: *mut c_void,
+}
+
+#[repr(C)]
+pub(crate) struct ArrowArrayStream {
+    get_schema: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowSchema) -> c_int>,
+    get_next: Option<unsafe extern "C" fn(*mut ArrowArrayStream, *mut ArrowArray) -> c_int>,
+    get_last_error: Option<unsafe extern "C" fn(*mut ArrowArrayStream) -> *const c_char>,
+    release: Option<unsafe extern "C" fn(*mut ArrowArrayStream)>,
+    private_data: *mut c_void,
+}
+
+/// The three C structs, which share the capsule and release conventions.
+trait CStruct: Sized {
+    /// Name of the PyCapsule holding this struct.
+    const CAPSULE: &'static CStr;
+
+    /// A released (empty) struct for a producer to fill.
+    fn released() -> Self;
+
+    fn is_released(&self) -> bool;
+
+    /// Calls the producer's release callback, if not released yet.
+    fn release(&mut self);
+}
+
+macro_rules! impl_c_struct {
+    ($ty:ty, $capsule:expr, $released:expr) => {
+        impl CStruct for $ty {
+            const CAPSULE: &'static CStr = $capsule;
+
+            fn released() -> Self {
+                $released
+            }
+
+            fn is_released(&self) -> bool {
+                self.release.is_none()
+            }
+
+            fn release(&mut self) {
+                if let Some(release) = self.release {
+                    // SAFETY: the producer's callback, on the struct it filled.
+                    unsafe { release(self) };
+                }
+            }
+        }
+    };
+}
+
+impl_c_struct!(
+    ArrowSchema,
+    c"arrow_schema",
+    ArrowSchema {
+        format: ptr::null(),
+        name: ptr::null(),
+        metadata: ptr::null(),
+        flags: 0,
+        n_children: 0,
+        children: ptr::null_mut(),
+        dictionary: ptr::null_mut(),
+        release: None,
+        private_data: ptr::null_mut(),
+    }
+);
+
+impl_c_struct!(
+    ArrowArray,
+    c"arrow_array",
+    ArrowArray {
+        length: 0,
+        null_count: 0,
+        offset: 0,
+        n_buffers: 0,
+        n_children: 0,
+        buffers: ptr::null_mut(),
+        children: ptr::null_mut(),
+        dictionary: ptr::null_mut(),
+        release: None,
+        private_data: ptr::null_mut(),
+    }
+);
+
+impl_c_struct!(
+    ArrowArrayStream,
+    c"arrow_array_stream",
+    ArrowArrayStream {
+        get_schema: None,
+        get_next: None,
+        get_last_error: None,
+        release: None,
+        private_data: ptr::null_mut(),
+    }
+);
+
+// --- Export ---------------------------------------------------------------
+
+/// Owns everything an exported schema node points to.
+struct SchemaPrivate {
+    format: &'static CStr,
+    name: &'static CStr,
+    children: Box<[*mut ArrowSchema]>,
+}
+
+fn schema_node(
+    format: &'static CStr,
+    name: &'static CStr,
+    flags: i64,
+    children: Vec<ArrowSchema>,
+) -> ArrowSchema {
+    let children: Box<[*mut ArrowSchema]> = children
+        .into_iter()
+        .map(|child| Box::into_raw(Box::new(child)))
+        .collect();
+    let mut private = Box::new(SchemaPrivate {
+        format,
+        name,
+        children,
+    });
+    ArrowSchema {
+        format: private.format.as_ptr(),
+        name: private.name.as_ptr(),
+        metadata: ptr::null(),
+        flags,
+        n_children: private.children.len() as i64,
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_schema),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+unsafe extern "C" fn release_schema(schema: *mut ArrowSchema) {
+    // SAFETY: only installed on nodes built by `schema_node`, whose private
+    // data and children are boxes leaked there.
+    unsafe {
+        let private = Box::from_raw((*schema).private_data.cast::<SchemaPrivate>());
+        for &child in private.children.iter() {
+            Box::from_raw(child).release();
+        }
+        (*schema).release = None;
+    }
+}
+
+/// Whether the keys need 64-bit offsets (`large_utf8`).
+fn large_keys(dict: &RedDict) -> bool {
+    dict.keys.iter().map(String::len).sum::<usize>() > i32::MAX as usize
+}
+
+fn export_schema(large: bool) -> ArrowSchema {
+    let key_format = if large { c"U" } else { c"u" };
+    schema_node(
+        c"+s",
+        c"",
+        0,
+        vec![
+            schema_node(key_format, c"key", 0, Vec::new()),
+            schema_node(c"g", c"value", FLAG_NULLABLE, Vec::new()),
+        ],
+    )
+}
+
+/// Owns (or keeps alive) everything an exported array node points to.
+struct ArrayPrivate {
+    buffers: Box<[*const c_void]>,
+    children: Box<[*mut ArrowArray]>,
+    _keep: Vec<Box<dyn Any>>,
+}
+
+fn array_node(
+    length: usize,
+    null_count: usize,
+    buffers: Vec<*const c_void>,
+    children: Vec<ArrowArray>,
+    keep: Vec<Box<dyn Any>>,
+) -> ArrowArray {
+    let children: Box<[*mut ArrowArray]> = children
+        .into_iter()
+        .map(|child| Box::into_raw(Box::new(child)))
+        .collect();
+    let mut private = Box::new(ArrayPrivate {
+        buffers: buffers.into_boxed_slice(),
+        children,
+        _keep: keep,
+    });
+    ArrowArray {
+        length: length as i64,
+        null_count: null_count as i64,
+        offset: 0,
+        n_buffers: private.buffers.len() as i64,
+        n_children: private.children.len() as i64,
+        buffers: private.buffers.as_mut_ptr(),
+        children: private.children.as_mut_ptr(),
+        dictionary: ptr::null_mut(),
+        release: Some(release_array),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+unsafe extern "C" fn release_array(array: *mut ArrowArray) {
+    // SAFETY: only installed on nodes built by `array_node`.
+    unsafe {
+        let private = Box::from_raw((*array).private_data.cast::<ArrayPrivate>());
+        for &child in private.children.iter() {
+            Box::from_raw(child).release();
+        }
+        (*array).release = None;
+    }
+}
+
+/// Arrow's variable-size binary layout: `keys.len() + 1` offsets into the
+/// concatenated bytes.
+fn key_buffers<O: TryFrom<usize>>(keys: &[String]) -> (Vec<O>, Vec<u8>) {
+    let mut offsets = Vec::with_capacity(keys.len() + 1);
+    let mut data = Vec::with_capacity(keys.iter().map(String::len).sum());
+    let offset = |len: usize| O::try_from(len).ok().expect("key offsets fit the type");
+    offsets.push(offset(0));
+    for key in keys {
+        data.extend_from_slice(key.as_bytes());
+        offsets.push(offset(data.len()));
+    }
+    (offsets, data)
+}
+
+fn export_array(dict: &RedDict, large: bool) -> ArrowArray {
+    let n = dict.keys.len();
+    let key_column = if large {
+        let (offsets, data) = key_buffers::<i64>(&dict.keys);
+        let buffers = vec![ptr::null(), offsets.as_ptr().cast(), data.as_ptr().cast()];
+        array_node(
+            n,
+            0,
+            buffers,
+            Vec::new(),
+            vec![Box::new(offsets), Box::new(data)],
+        )
+    } else {
+        let (offsets, data) = key_buffers::<i32>(&dict.keys);
+        let buffers = vec![ptr::null(), offsets.as_ptr().cast(), data.as_ptr().cast()];
+        array_node(
+            n,
+            0,
+            buffers,
+            Vec::new(),
+            vec![Box::new(offsets), Box::new(data)],
+        )
+    };
+
+    let values = Arc::clone(&dict.values);
+    let value_column = match &dict.validity {
+        Some(validity) => {
+            let bitmap = validity.to_bytes();
+            let buffers = vec![bitmap.as_ptr().cast(), values.as_ptr().cast()];
+            let keep: Vec<Box<dyn Any>> = vec![Box::new(bitmap), Box::new(values)];
+            array_node(n, validity.count_unset(), buffers, Vec::new(), keep)
+        }
+        None => {
+            let buffers = vec![ptr::null(), values.as_ptr().cast()];
+            array_node(n, 0, buffers, Vec::new(), vec![Box::new(values)])
+        }
+    };
+    array_node(
+        n,
+        0,
+        vec![ptr::null()],
+        vec![key_column, value_column],
+        Vec::new(),
+    )
+}
+
+/// State behind an exported stream: the single batch, until it is taken.
+struct StreamPrivate {
+    pending: Option<RedDict>,
+    large: bool,
+}
+
+unsafe extern "C" fn stream_get_schema(
+    stream: *mut ArrowArrayStream,
+    out: *mut ArrowSchema,
+) -> c_int {
+    // SAFETY: `stream` was built by `export_stream`; `out` is the consumer's.
+    unsafe {
+        let private = &*(*stream).private_data.cast::<StreamPrivate>();
+        out.write(export_schema(private.large));
+    }
+    0
+}
+
+unsafe extern "C" fn stream_get_next(stream: *mut ArrowArrayStream, out: *mut ArrowArray) -> c_int {
+    // SAFETY: as in `stream_get_schema`.
+    unsafe {
+        let private = &mut *(*stream).private_data.cast::<StreamPrivate>();
+        out.write(match private.pending.take() {
+            Some(dict) => export_array(&dict, private.large),
+            // A released array marks the end of the stream.
+            None => ArrowArray::released(),
+        });
+    }
+    0
+}
+
+unsafe extern "C" fn stream_get_last_error(_stream: *mut ArrowArrayStream) -> *const c_char {
+    ptr::null()
+}
+
+unsafe extern "C" fn release_stream(stream: *mut ArrowArrayStream) {
+    // SAFETY: `private_data` is the box leaked by `export_stream`.
+    unsafe {
+        drop(Box::from_raw(
+            (*stream).private_data.cast::<StreamPrivate>(),
+        ));
+        (*stream).release = None;
+    }
+}
+
+fn export_stream(dict: &RedDict) -> ArrowArrayStream {
+    let private = Box::new(StreamPrivate {
+        pending: Some(dict.clone()),
+        large: large_keys(dict),
+    });
+    ArrowArrayStream {
+        get_schema: Some(stream_get_schema),
+        get_next: Some(stream_get_next),
+        get_last_error: Some(stream_get_last_error),
+        release: Some(release_stream),
+        private_data: Box::into_raw(private).cast(),
+    }
+}
+
+/// Capsule destructor: releases the struct unless a consumer moved it out.
+unsafe extern "C" fn drop_capsule<T: CStruct>(capsule: *mut ffi::PyObject) {
+    // SAFETY: installed by `into_capsule` on a capsule holding a leaked
+    // `Box<T>` under `T::CAPSULE`.
+    unsafe {
+        let value = ffi::PyCapsule_GetPointer(capsule, T::CAPSULE.as_ptr()).cast::<T>();
+        if value.is_null() {
+            ffi::PyErr_Clear();
+            return;
+        }
+        Box::from_raw(value).release();
+    }
+}
+
+fn into_capsule<T: CStruct>(py: Python, value: T) -> PyResult<Bound<PyCapsule>> {
+    let value = Box::into_raw(Box::new(value));
+    // SAFETY: the capsule takes ownership of `value`, freed by `drop_capsule`.
+    unsafe {
+        let capsule =
+            ffi::PyCapsule_New(value.cast(), T::CAPSULE.as_ptr(), Some(drop_capsule::<T>));
+        if capsule.is_null() {
+            Box::from_raw(value).release();
+            return Err(PyErr::fetch(py));
+        }
+        Ok(Bound::from_owned_ptr(py, capsule).cast_into_unchecked())
+    }
+}
+
+/// `(schema capsule, array capsule)` for `__arrow_c_array__`.
+pub(crate) fn array_capsules<'py>(
+    py: Python<'py>,
+    dict: &RedDict,
+) -> PyResult<Bound<'py, PyTuple>> {
+    let large = large_keys(dict);
+    let schema = into_capsule(py, export_schema(large))?;
+    let array = into_capsule(py, export_array(dict, large))?;
+    PyTuple::new(py, [schema, array])
+}
+
+/// The stream capsule for `__arrow_c_stream__`.
+pub(crate) fn stream_capsule<'py>(
+    py: Python<'py>,
+    dict: &RedDict,
+) -> PyResult<Bound<'py, PyCapsule>> {
+    into_capsule(py, export_stream(dict))
+}
+
+// --- Import ---------------------------------------------------------------
+
+/// A struct moved out of a consumer-side capsule, released when dropped.
+struct Imported<T: CStruct>(T);
+
+impl<T: CStruct> Drop for Imported<T> {
+    fn drop(&mut self) {
+        self.0.release();
+    }
+}
+
+/// Moves the struct out of `capsule`, leaving a released one behind so the
+/// capsule's destructor does nothing.
+fn take_capsule<T: CStruct>(capsule: &Bound<PyAny>) -> PyResult<Imported<T>> {
+    let capsule = capsule.cast::<PyCapsule>()?;
+    // SAFETY: a capsule named `T::CAPSULE` holds a `T` per the protocol.
+    unsafe {
+        let value = ffi::PyCapsule_GetPointer(capsule.as_ptr(), T::CAPSULE.as_ptr()).cast::<T>();
+        if value.is_null() {
+            return Err(PyErr::fetch(capsule.py()));
+        }
+        if (*value).is_released() {
+            return Err(PyValueError::new_err("Arrow capsule was already consumed"));
+        }
+        Ok(Imported(value.replace(T::released())))
+    }
+}
+
+/// Columns read so far, across batches.
+#[derive(Default)]
+struct Columns {
+    keys: Vec<String>,
+    values: Vec<f64>,
+    valid: Vec<bool>,
+}
+
+/// A type Arrow stores as fixed-width values convertible to `f64`.
+#[derive(Clone, Copy)]
+enum ValueType {
+    Float64,
+    Float32,
+    Int64,
+    Int32,
+}
+
+/// The child schemas and positions of the key and value columns.
+fn columns(schema: &ArrowSchema) -> PyResult<[(usize, &ArrowSchema); 2]> {
+    // SAFETY: a live schema's strings and children are valid.
+    unsafe {
+        if CStr::from_ptr(schema.format) != c"+s" {
+            return Err(PyTypeError::new_err(
+                "expected an Arrow struct array or record batch",
+            ));
+        }
+        let children: Vec<&ArrowSchema> = (0..schema.n_children as usize)
+            .map(|i| &**schema.children.add(i))
+            .collect();
+        let position = |name: &CStr| {
+            children
+                .iter()
+                .position(|c| !c.name.is_null() && CStr::from_ptr(c.name) == name)
+        };
+        match (position(c"key"), position(c"value")) {
+            (Some(k), Some(v)) => Ok([(k, children[k]), (v, children[v])]),
+            _ if children.len() == 2 => Ok([(0, children[0]), (1, children[1])]),
+            _ => Err(PyValueError::new_err(
+                "expected 'key' and 'value' columns, or exactly two columns",
+            )),
+        }
+    }
+}
+
+/// Bit `i` of an Arrow validity bitmap; a null bitmap means all valid.
+///
+/// # Safety
+///
+/// A non-null `bitmap` must cover bit `i`.
+unsafe fn bit(bitmap: *const u8, i: usize) -> bool {
+    bitmap.is_null() || unsafe { *bitmap.add(i / 8) } & (1 << (i % 8)) != 0
+}
+
+/// # Safety
+///
+/// `array` must be a live array of at least `k + 1` buffers.
+unsafe fn buffer<T>(array: &ArrowArray, k: usize) -> *const T {
+    unsafe { (*array.buffers.add(k)).cast() }
+}
+
+/// Reads one struct array (a record batch) into `columns`.
+///
+/// # Safety
+///
+/// `schema` and `array` must be live and describe each other.
+unsafe fn read_batch(schema: &ArrowSchema, array: &ArrowArray, out: &mut Columns) -> PyResult<()> {
+    let [(key_pos, key_schema), (value_pos, value_schema)] = columns(schema)?;
+    let len = array.length as usize;
+    let base = array.offset as usize;
+    // SAFETY: the caller guarantees the array matches its schema, so the
+    // buffers hold what each format says, for `offset + length` entries.
+    unsafe {
+        let struct_validity = buffer::<u8>(array, 0);
+        if (0..len).any(|i| !bit(struct_validity, base + i)) {
+            return Err(PyValueError::new_err("Arrow record batch has null rows"));
+        }
+        let key_array = &**array.children.add(key_pos);
+        let value_array = &**array.children.add(value_pos);
+        if !key_schema.dictionary.is_null() || !value_schema.dictionary.is_null() {
+            return Err(PyTypeError::new_err(
+                "dictionary-encoded Arrow columns are not supported",
+            ));
+        }
+
+        let key_format = CStr::from_ptr(key_schema.format).to_bytes();
+        let key_start = key_array.offset as usize + base;
+        let key_validity = buffer::<u8>(key_array, 0);
+        out.keys.reserve(len);
+        for i in key_start..key_start + len {
+            if !bit(key_validity, i) {
+                return Err(PyValueError::new_err("Arrow key column contains nulls"));
+            }
+            let bytes = match key_format {
+                b"u" => {
+                    let offsets = buffer::<i32>(key_array, 1);
+                    let (start, end) = (*offsets.add(i) as usize, *offsets.add(i + 1) as usize);
+                    std::slice::from_raw_parts(buffer::<u8>(key_array, 2).add(start), end - start)
+                }
+                b"U" => {
+                    let offsets = buffer::<i64>(key_array, 1);
+                    let (start, end) = (*offsets.add(i) as usize, *offsets.add(i + 1) as usize);
+                    std::slice::from_raw_parts(buffer::<u8>(key_array, 2).add(start), end - start)
+                }
+                b"vu" => view_bytes(key_array, i),
+                _ => {
+                    return Err(PyTypeError::new_err(format!(
+                        "unsupported Arrow key type {:?}",
+                        String::from_utf8_lossy(key_format)
+                    )))
+                }
+            };
+            let key = std::str::from_utf8(bytes)
+                .map_err(|_| PyValueError::new_err("Arrow key is not valid UTF-8"))?;
+            out.keys.push(key.to_string());
+        }
+
+        let value_type = match CStr::from_ptr(value_schema.format).to_bytes() {
+            b"g" => ValueType::Float64,
+            b"f" => ValueType::Float32,
+            b"l" => ValueType::Int64,
+            b"i" => ValueType::Int32,
+            other => {
+                return Err(PyTypeError::new_err(format!(
+                    "unsupported Arrow value type {:?}",
+                    String::from_utf8_lossy(other)
+                )))
+            }
+        };
+        let value_start = value_array.offset as usize + base;
+        let value_validity = buffer::<u8>(value_array, 0);
+        out.values.reserve(len);
+        out.valid.reserve(len);
+        for i in value_start..value_start + len {
+            let valid = bit(value_validity, i);
+            out.valid.push(valid);
+            out.values.push(if !valid {
+                f64::NAN
+            } else {
+                match value_type {
+                    ValueType::Float64 => *buffer::<f64>(value_array, 1).add(i),
+                    ValueType::Float32 => f64::from(*buffer::<f32>(value_array, 1).add(i)),
+                    ValueType::Int64 => *buffer::<i64>(value_array, 1).add(i) as f64,
+                    ValueType::Int32 => f64::from(*buffer::<i32>(value_array, 1).add(i)),
+                }
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Bytes of entry `i` of a `utf8_view` array: 16-byte views holding a
+/// length, then either the string inline (up to 12 bytes) or a prefix, a
+/// data buffer index and an offset into it.
+///
+/// # Safety
+///
+/// `array` must be a live `utf8_view` array covering entry `i`.
+unsafe fn view_bytes(array: &ArrowArray, i: usize) -> &[u8] {
+    unsafe {
+        let view = buffer::<u8>(array, 1).add(i * 16);
+        let len = i32::from_le_bytes(*view.cast::<[u8; 4]>()) as usize;
+        if len <= 12 {
+            return std::slice::from_raw_parts(view.add(4), len);
+        }
+        let index = i32::from_le_bytes(*view.add(8).cast::<[u8; 4]>()) as usize;
+        let offset = i32::from_le_bytes(*view.add(12).cast::<[u8; 4]>()) as usize;
+        std::slice::from_raw_parts(buffer::<u8>(array, 2 + index).add(offset), len)
+    }
+}
+
+/// Reads every batch of an Arrow stream.
+fn read_stream(stream: &mut Imported<ArrowArrayStream>, out: &mut Columns) -> PyResult<()> {
+    let stream = &mut stream.0;
+    let error = |stream: &mut ArrowArrayStream, code: c_int| {
+        // SAFETY: `get_last_error` returns null or a string valid until the
+        // next call on the stream.
+        let message = unsafe {
+            stream
+                .get_last_error
+                .map(|f| f(stream))
+                .filter(|m| !m.is_null())
+                .map(|m| CStr::from_ptr(m).to_string_lossy().into_owned())
+        };
+        PyValueError::new_err(format!(
+            "Arrow stream failed: {}",
+            message.unwrap_or_else(|| format!("error code {code}"))
+        ))
+    };
+    let (Some(get_schema), Some(get_next)) = (stream.get_schema, stream.get_next) else {
+        return Err(PyValueError::new_err("Arrow stream has no callbacks"));
+    };
+
+    let mut schema = Imported(ArrowSchema::released());
+    // SAFETY: the producer's callbacks, on its own stream.
+    let code = unsafe { get_schema(stream, &mut schema.0) };
+    if code != 0 {
+        return Err(error(stream, code));
+    }
+    loop {
+        let mut array = Imported(ArrowArray::released());
+        let code = unsafe { get_next(stream, &mut array.0) };
+        if code != 0 {
+            return Err(error(stream, code));
+        }
+        if array.0.is_released() {
+            return Ok(());
+        }
+        // SAFETY: batches of a stream match its schema.
+        unsafe { read_batch(&schema.0, &array.0, out)? };
+    }
+}
+
+/// Builds a RedDict from any object implementing the Arrow PyCapsule
+/// interface (`__arrow_c_array__` or `__arrow_c_stream__`).
+pub(crate) fn from_arrow(obj: &Bound<PyAny>) -> PyResult<RedDict> {
+    let mut columns = Columns::default();
+    if obj.hasattr("__arrow_c_array__")? {
+        let capsules = obj.call_method0("__arrow_c_array__")?;
+        let (schema, array): (Bound<PyAny>, Bound<PyAny>) = capsules.extract()?;
+        let schema = take_capsule::<ArrowSchema>(&schema)?;
+        let array = take_capsule::<ArrowArray>(&array)?;
+        // SAFETY: the producer pairs the array with its schema.
+        unsafe { read_batch(&schema.0, &array.0, &mut columns)? };
+    } else if obj.hasattr("__arrow_c_stream__")? {
+        let capsule = obj.call_method0("__arrow_c_stream__")?;
+        read_stream(
+            &mut take_capsule::<ArrowArrayStream>(&capsule)?,
+            &mut columns,
+        )?;
+    } else {
+        return Err(PyTypeError::new_err(format!(
+            "expected an object implementing the Arrow PyCapsule interface, got {}",
+            obj.get_type().name()?
+        )));
+    }
+
+    let Columns {
+        keys,
+        values,
+        valid,
+    } = columns;
+    let schema = KeySchema::new(keys)?;
+    Ok(RedDict {
+        keys: schema.keys,
+        index: schema.index,
+        values: Arc::new(values),
+        validity: None,
+        frozen: false,
+    }
+    .with_validity(Bitmap::from_fn(valid.len(), |i| valid[i])))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::make_dict;
+
+    #[test]
+    fn test_exported_array_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("ab", Some(1.0)), ("", None), ("c", Some(3.0))]);
+            let mut schema = export_schema(false);
+            let mut array = export_array(&rd, false);
+            unsafe {
+                assert_eq!(CStr::from_ptr(schema.format), c"+s");
+                let value_schema = &**schema.children.add(1);
+                assert_eq!(CStr::from_ptr(value_schema.name), c"value");
+                assert_eq!(value_schema.flags, FLAG_NULLABLE);
+
+                assert_eq!((array.length, array.n_children, array.n_buffers), (3, 2, 1));
+                let keys = &**array.children;
+                let offsets = std::slice::from_raw_parts(buffer::<i32>(keys, 1), 4);
+                assert_eq!(offsets, [0, 2, 2, 3]);
+                let values = &**array.children.add(1);
+                assert_eq!(values.null_count, 1);
+                assert_eq!(*buffer::<u8>(values, 0), 0b101);
+                // The values buffer is shared, not copied.
+                assert_eq!(buffer::<f64>(values, 1), rd.values.as_ptr());
+            }
+            schema.release();
+            array.release();
+            assert!(schema.is_released() && array.is_released());
+            assert_eq!(Arc::strong_count(&rd.values), 1);
+        });
+    }
+
+    /// A hand-built batch with `large_utf8` keys, `int64` values and
+    /// non-zero offsets, as other producers may send.
+    #[test]
+    fn test_read_batch_handles_offsets_and_other_types() {
+        Python::initialize();
+        let offsets: [i64; 4] = [0, 1, 3, 6];
+        let data = b"xyyzzz";
+        let values: [i64; 3] = [7, 8, 9];
+        let validity = [0b110u8];
+        let key_buffers = [ptr::null(), offsets.as_ptr().cast(), data.as_ptr().cast()];
+        let value_buffers: [*const c_void; 2] = [validity.as_ptr().cast(), values.as_ptr().cast()];
+
+        let keys = array_node(3, 0, key_buffers.to_vec(), Vec::new(), Vec::new());
+        let mut values_array = array_node(3, 1, value_buffers.to_vec(), Vec::new(), Vec::new());
+        values_array.offset = 0;
+        let mut batch = array_node(
+            2,
+            0,
+            vec![ptr::null()],
+            vec![keys, values_array],
+            Vec::new(),
+        );
+        batch.offset = 1;
+        let schema = schema_node(
+            c"+s",
+            c"",
+            0,
+            vec![
+                schema_node(c"U", c"key", 0, Vec::new()),
+                schema_node(c"l", c"value", FLAG_NULLABLE, Vec::new()),
+            ],
+        );
+
+        let mut columns = Columns::default();
+        unsafe { read_batch(&schema, &batch, &mut columns).unwrap() };
+        assert_eq!(columns.keys, ["yy", "zzz"]);
+        assert_eq!(columns.values, [8.0, 9.0]);
+        assert_eq!(columns.valid, [true, true]);
+        drop(Imported(schema));
+        drop(Imported(batch));
+    }
+
+    #[test]
+    fn test_view_bytes_reads_inline_and_buffered_strings() {
+        let long = b"a string longer than twelve";
+        let mut views = [0u8; 32];
+        views[..4].copy_from_slice(&5i32.to_le_bytes());
+        views[4..9].copy_from_slice(b"short");
+        views[16..20].copy_from_slice(&(long.len() as i32).to_le_bytes());
+        views[20..24].copy_from_slice(&long[..4]);
+        views[24..28].copy_from_slice(&0i32.to_le_bytes());
+        views[28..32].copy_from_slice(&0i32.to_le_bytes());
+        let sizes = [long.len() as i64];
+        let buffers = vec![
+            ptr::null(),
+            views.as_ptr().cast(),
+            long.as_ptr().cast(),
+            sizes.as_ptr().cast(),
+        ];
+        let array = Imported(array_node(2, 0, buffers, Vec::new(), Vec::new()));
+        unsafe {
+            assert_eq!(view_bytes(&array.0, 0), b"short");
+            assert_eq!(view_bytes(&array.0, 1), long);
+        }
+    }
+}
@@ -0,0 +1,151 @@
+//! Packed validity masks for RedDicts with missing values.
+
+/// A packed bit mask: bit `i` is set when entry `i` holds a value.
+///
+/// Bits are numbered least-significant first within `u64` words, which on
+/// little-endian targets is the byte layout Arrow uses for validity buffers.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Bitmap {
+    words: Vec<u64>,
+    len: usize,
+}
+
+impl Bitmap {
+    /// A mask of `len` bits, all set.
+    pub(crate) fn new_valid(len: usize) -> Self {
+        let mut words = vec![u64::MAX; len.div_ceil(64)];
+        let tail = len % 64;
+        if tail > 0 {
+            if let Some(last) = words.last_mut() {
+                *last = (1 << tail) - 1;
+            }
+        }
+        Self { words, len }
+    }
+
+    /// A mask of `len` bits where bit `i` is `f(i)`.
+    pub(crate) fn from_fn<F>(len: usize, mut f: F) -> Self
+    where
+        F: FnMut(usize) -> bool,
+    {
+        let mut bitmap = Self {
+            words: vec![0; len.div_ceil(64)],
+            len,
+        };
+        for i in 0..len {
+            if f(i) {
+                bitmap.words[i / 64] |= 1 << (i % 64);
+            }
+        }
+        bitmap
+    }
+
+    pub(crate) fn get(&self, i: usize) -> bool {
+        self.words[i / 64] & (1 << (i % 64)) != 0
+    }
+
+    pub(crate) fn set(&mut self, i: usize, valid: bool) {
+        if valid {
+            self.words[i / 64] |= 1 << (i % 64);
+        } else {
+            self.words[i / 64] &= !(1 << (i % 64));
+        }
+    }
+
+    /// Appends one bit.
+    pub(crate) fn push(&mut self, valid: bool) {
+        if self.len == self.words.len() * 64 {
+            self.words.push(0);
+        }
+        self.len += 1;
+        self.set(self.len - 1, valid);
+    }
+
+    /// Removes bit `i`, shifting the bits after it down by one.
+    pub(crate) fn remove(&mut self, i: usize) {
+        let shifted = Self::from_fn(self.len - 1, |j| self.get(if j < i { j } else { j + 1 }));
+        *self = shifted;
+    }
+
+    /// The mask as `len.div_ceil(8)` bytes, least-significant bit first.
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = self.words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        bytes.truncate(self.len.div_ceil(8));
+        bytes
+    }
+
+    /// The inverse of `to_bytes`; `None` when `bytes` is too short for `len`
+    /// bits. Bits past `len` are ignored.
+    pub(crate) fn from_bytes(bytes: &[u8], len: usize) -> Option<Self> {
+        if bytes.len() < len.div_ceil(8) {
+            return None;
+        }
+        Some(Self::from_fn(len, |i| bytes[i / 8] & (1 << (i % 8)) != 0))
+    }
+
+    /// Number of unset bits.
+    pub(crate) fn count_unset(&self) -> usize {
+        let set: usize = self.words.iter().map(|w| w.count_ones() as usize).sum();
+        self.len - set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_valid_sets_exactly_len_bits() {
+        for len in [0, 1, 63, 64, 65, 130] {
+            let bitmap = Bitmap::new_valid(len);
+            assert_eq!(bitmap.count_unset(), 0, "len {len}");
+            assert!((0..len).all(|i| bitmap.get(i)));
+        }
+    }
+
+    #[test]
+    fn test_set_and_get() {
+        let mut bitmap = Bitmap::new_valid(70);
+        bitmap.set(3, false);
+        bitmap.set(69, false);
+        assert!(!bitmap.get(3));
+        assert!(!bitmap.get(69));
+        assert!(bitmap.get(68));
+        assert_eq!(bitmap.count_unset(), 2);
+        bitmap.set(3, true);
+        assert_eq!(bitmap.count_unset(), 1);
+    }
+
+    #[test]
+    fn test_from_fn_matches_new_valid() {
+        assert_eq!(Bitmap::from_fn(100, |_| true), Bitmap::new_valid(100));
+        let evens = Bitmap::from_fn(100, |i| i % 2 == 0);
+        assert_eq!(evens.count_unset(), 50);
+        assert!(evens.get(0) && !evens.get(99));
+    }
+
+    #[test]
+    fn test_push_and_remove() {
+        let mut bitmap = Bitmap::new_valid(63);
+        bitmap.push(false);
+        bitmap.push(true);
+        assert!(!bitmap.get(63) && bitmap.get(64));
+        assert_eq!(bitmap.count_unset(), 1);
+        bitmap.set(10, false);
+        bitmap.remove(10);
+        assert!(!bitmap.get(62) && bitmap.get(63));
+        assert_eq!(bitmap.count_unset(), 1);
+        assert_eq!(bitmap, Bitmap::from_fn(64, |i| i != 62));
+    }
+
+    #[test]
+    fn test_bytes_round_trip() {
+        let bitmap = Bitmap::from_fn(70, |i| i % 3 != 0);
+        let bytes = bitmap.to_bytes();
+        assert_eq!(bytes.len(), 9);
+        assert_eq!(bytes[0], 0b1011_0110);
+        assert_eq!(Bitmap::from_bytes(&bytes, 70), Some(bitmap));
+        assert_eq!(Bitmap::from_bytes(&[0xff], 3), Some(Bitmap::new_valid(3)));
+        assert_eq!(Bitmap::from_bytes(&bytes, 80), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! One-pass construction of RedDicts from Python iterables.
+//!
+//! `Builder` grows the keys, index, values and validity together as entries
+//! arrive, so every key is hashed exactly once whatever the source: a dict,
+//! `(key, value)` pairs, or separate key and value iterables.
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+use crate::{bitmap::Bitmap, RedDict};
+
+/// What to do when a key arrives a second time.
+#[derive(Clone, Copy)]
+pub(crate) enum OnDuplicate {
+    /// Raise `ValueError`.
+    Error,
+    /// Add the values up, skipping nulls; null only if every value is.
+    Sum,
+    /// Keep the latest value, null or not, as `dict` does.
+    Last,
+    /// Keep the earliest value.
+    First,
+}
+
+impl OnDuplicate {
+    pub(crate) fn parse(on_duplicate: &str) -> PyResult<Self> {
+        match on_duplicate {
+            "error" => Ok(OnDuplicate::Error),
+            "sum" => Ok(OnDuplicate::Sum),
+            "last" => Ok(OnDuplicate::Last),
+            "first" => Ok(OnDuplicate::First),
+            _ => Err(PyValueError::new_err(format!(
+                "on_duplicate must be 'error', 'sum', 'last' or 'first', got {on_duplicate:?}"
+            ))),
+        }
+    }
+}
+
+/// A RedDict under construction, with keys in first-seen order.
+pub(crate) struct Builder {
+    keys: Vec<String>,
+    index: HashMap<String, usize>,
+    values: Vec<f64>,
+    validity: Bitmap,
+    on_duplicate: OnDuplicate,
+}
+
+impl Builder {
+    /// An empty builder with room for `capacity` keys.
+    pub(crate) fn with_capacity(capacity: usize, on_duplicate: OnDuplicate) -> Self {
+        Self {
+            keys: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+            values: Vec::with_capacity(capacity),
+            validity: Bitmap::new_valid(0),
+            on_duplicate,
+        }
+    }
+
+    /// Adds one entry, resolving a repeated key by the builder's policy.
+    pub(crate) fn push(&mut self, key: String, value: Option<f64>) -> PyResult<()> {
+        match self.index.entry(key) {
+            Entry::Vacant(entry) => {
+                self.keys.push(entry.key().clone());
+                entry.insert(self.values.len());
+                self.values.push(value.unwrap_or(f64::NAN));
+                self.validity.push(value.is_some());
+            }
+            Entry::Occupied(entry) => {
+                let pos = *entry.get();
+                match (self.on_duplicate, value) {
+                    (OnDuplicate::Error, _) => {
+                        return Err(PyValueError::new_err(format!(
+                            "duplicate key {:?}",
+                            entry.key()
+                        )));
+                    }
+                    (OnDuplicate::First, _) | (OnDuplicate::Sum, None) => {}
+                    (OnDuplicate::Sum, Some(v)) if self.validity.get(pos) => {
+                        self.values[pos] += v;
+                    }
+                    (OnDuplicate::Sum | OnDuplicate::Last, value) => {
+                        self.values[pos] = value.unwrap_or(f64::NAN);
+                        self.validity.set(pos, value.is_some());
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn finish(self) -> RedDict {
+        RedDict {
+            keys: Arc::new(self.keys),
+            index: Arc::new(self.index),
+            values: Arc::new(self.values),
+            validity: None,
+            frozen: false,
+        }
+        .with_validity(self.validity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(on_duplicate: OnDuplicate, entries: &[(&str, Option<f64>)]) -> PyResult<RedDict> {
+        let mut builder = Builder::with_capacity(entries.len(), on_duplicate);
+        for (k, v) in entries {
+            builder.push(k.to_string(), *v)?;
+        }
+        Ok(builder.finish())
+    }
+
+    fn entries(dict: &RedDict) -> Vec<(String, Option<f64>)> {
+        dict.keys
+            .iter()
+            .enumerate()
+            .map(|(pos, k)| {
+                let valid = dict.validity.as_ref().is_none_or(|v| v.get(pos));
+                (k.clone(), valid.then_some(dict.values[pos]))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_builder_resolves_duplicates() {
+        Python::initialize();
+        let pairs = [
+            ("a", Some(1.0)),
+            ("b", None),
+            ("a", Some(2.0)),
+            ("b", Some(3.0)),
+            ("a", None),
+        ];
+        let expect =
+            |a: Option<f64>, b: Option<f64>| vec![("a".to_string(), a), ("b".to_string(), b)];
+
+        let sum = build(OnDuplicate::Sum, &pairs).unwrap();
+        assert_eq!(entries(&sum), expect(Some(3.0), Some(3.0)));
+        let last = build(OnDuplicate::Last, &pairs).unwrap();
+        assert_eq!(entries(&last), expect(None, Some(3.0)));
+        let first = build(OnDuplicate::First, &pairs).unwrap();
+        assert_eq!(entries(&first), expect(Some(1.0), None));
+        assert!(build(OnDuplicate::Error, &pairs).is_err());
+        assert_eq!(sum.index["b"], 1);
+    }
+
+    #[test]
+    fn test_parse_on_duplicate() {
+        Python::initialize();
+        assert!(matches!(OnDuplicate::parse("sum"), Ok(OnDuplicate::Sum)));
+        assert!(OnDuplicate::parse("max").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Text and HTML renderings of a RedDict.
+//!
+//! Keys and values are formatted with Python's own `repr`, so a short
+//! RedDict prints exactly like the equivalent dict. Long ones show their
+//! first and last entries around an ellipsis.
+use std::fmt::Write;
+
+use pyo3::{
+    prelude::*,
+    types::{PyFloat, PyString},
+};
+
+use crate::RedDict;
+
+/// Positions to show for `len` entries when at most `max_items` fit: the
+/// first and last few, with `None` marking the elided middle.
+fn shown(len: usize, max_items: usize) -> Vec<Option<usize>> {
+    if len <= max_items {
+        return (0..len).map(Some).collect();
+    }
+    let head = max_items.div_ceil(2);
+    let tail = max_items / 2;
+    (0..head)
+        .map(Some)
+        .chain([None])
+        .chain((len - tail..len).map(Some))
+        .collect()
+}
+
+fn value_repr(py: Python, value: Option<f64>) -> PyResult<String> {
+    match value {
+        Some(v) => Ok(PyFloat::new(py, v).repr()?.to_string()),
+        None => Ok("None".to_string()),
+    }
+}
+
+/// `RedDict({'a': 1.0, ...}, len=N)`.
+pub(crate) fn repr(py: Python, dict: &RedDict, max_items: usize) -> PyResult<String> {
+    let mut entries = Vec::new();
+    for pos in shown(dict.keys.len(), max_items) {
+        entries.push(match pos {
+            Some(i) => format!(
+                "{}: {}",
+                PyString::new(py, &dict.keys[i]).repr()?,
+                value_repr(py, dict.value_at(i))?
+            ),
+            None => "...".to_string(),
+        });
+    }
+    let frozen = if dict.frozen { ", frozen=True" } else { "" };
+    Ok(format!(
+        "RedDict({{{}}}, len={}{frozen})",
+        entries.join(", "),
+        dict.keys.len()
+    ))
+}
+
+/// Key and value cells for the tabular forms, and whether rows were elided.
+fn rows(py: Python, dict: &RedDict, max_items: usize) -> PyResult<(Vec<(String, String)>, bool)> {
+    let shown = shown(dict.keys.len(), max_items);
+    let truncated = shown.contains(&None);
+    let mut rows = Vec::with_capacity(shown.len());
+    for pos in shown {
+        rows.push(match pos {
+            Some(i) => (dict.keys[i].clone(), value_repr(py, dict.value_at(i))?),
+            None => ("...".to_string(), "...".to_string()),
+        });
+    }
+    Ok((rows, truncated))
+}
+
+/// One line per entry, keys left-aligned and values right-aligned.
+pub(crate) fn table(py: Python, dict: &RedDict, max_items: usize) -> PyResult<String> {
+    if dict.keys.is_empty() {
+        return Ok("Empty RedDict".to_string());
+    }
+    let (rows, truncated) = rows(py, dict, max_items)?;
+    let key_width = rows
+        .iter()
+        .map(|(k, _)| k.chars().count())
+        .max()
+        .unwrap_or(0);
+    let value_width = rows
+        .iter()
+        .map(|(_, v)| v.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    let mut lines: Vec<String> = rows
+        .iter()
+        .map(|(k, v)| format!("{k:<key_width$}  {v:>value_width$}"))
+        .collect();
+    if truncated {
+        lines.push(format!("Length: {}", dict.keys.len()));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// An HTML table for Jupyter's rich display.
+pub(crate) fn html(py: Python, dict: &RedDict, max_items: usize) -> PyResult<String> {
+    let (rows, truncated) = rows(py, dict, max_items)?;
+    let mut out =
+        String::from("<table>\n<thead><tr><th>key</th><th>value</th></tr></thead>\n<tbody>\n");
+    for (k, v) in &rows {
+        // Writing to a String cannot fail.
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td>{}</td></tr>",
+            escape_html(k),
+            escape_html(v)
+        );
+    }
+    out.push_str("</tbody>\n</table>");
+    if truncated {
+        let _ = write!(out, "\n<p>Length: {}</p>", dict.keys.len());
+    }
+    Ok(out)
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::make_dict;
+    use pyo3::types::PyDict;
+
+    fn numbered(py: Python, len: usize) -> RedDict {
+        let dict = PyDict::new(py);
+        for i in 0..len {
+            dict.set_item(format!("k{i}"), i as f64).unwrap();
+        }
+        RedDict::py_new(Some(&dict), None, None).unwrap()
+    }
+
+    #[test]
+    fn test_shown_keeps_head_and_tail() {
+        assert_eq!(shown(3, 5), [Some(0), Some(1), Some(2)]);
+        assert_eq!(shown(10, 4), [Some(0), Some(1), None, Some(8), Some(9)]);
+        assert_eq!(shown(10, 3), [Some(0), Some(1), None, Some(9)]);
+        assert_eq!(shown(2, 0), [None]);
+    }
+
+    #[test]
+    fn test_repr_matches_python_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(1.0)), ("it's", None), ("big", Some(1e16))]);
+            assert_eq!(
+                repr(py, &rd, 10).unwrap(),
+                r#"RedDict({'a': 1.0, "it's": None, 'big': 1e+16}, len=3)"#
+            );
+            assert_eq!(
+                repr(py, &rd.freeze(), 10).unwrap(),
+                r#"RedDict({'a': 1.0, "it's": None, 'big': 1e+16}, len=3, frozen=True)"#
+            );
+            assert_eq!(
+                repr(py, &make_dict::<f64>(py, &[]), 10).unwrap(),
+                "RedDict({}, len=0)"
+            );
+        });
+    }
+
+    #[test]
+    fn test_repr_truncates_long_dicts() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = numbered(py, 100);
+            assert_eq!(
+                repr(py, &rd, 4).unwrap(),
+                "RedDict({'k0': 0.0, 'k1': 1.0, ..., 'k98': 98.0, 'k99': 99.0}, len=100)"
+            );
+        });
+    }
+
+    #[test]
+    fn test_table_aligns_columns() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(
+                py,
+                &[("a", Some(1.5)), ("long key", Some(-20.25)), ("b", None)],
+            );
+            assert_eq!(
+                table(py, &rd, 10).unwrap(),
+                "a            1.5\nlong key  -20.25\nb           None"
+            );
+            assert_eq!(
+                table(py, &numbered(py, 12), 2).unwrap(),
+                "k0    0.0\n...   ...\nk11  11.0\nLength: 12"
+            );
+            assert_eq!(
+                table(py, &make_dict::<f64>(py, &[]), 10).unwrap(),
+                "Empty RedDict"
+            );
+        });
+    }
+
+    #[test]
+    fn test_html_escapes_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("<b>&", Some(1.0))]);
+            let rendered = html(py, &rd, 10).unwrap();
+            assert!(rendered.contains("<tr><td>&lt;b&gt;&amp;</td><td>1.0</td></tr>"));
+            assert!(!rendered.contains("Length"));
+            let truncated = html(py, &numbered(py, 5), 2).unwrap();
+            assert!(truncated.ends_with("<p>Length: 5</p>"));
+        });
+    }
+}
@@ -0,0 +1,523 @@
+//! Lazy, fused expressions over RedDicts.
+//!
+//! Chaining eager operations allocates and fills a new `values` buffer per
+//! step. An [`Expr`] instead records the chain as a tree and, on `collect`,
+//! aligns every RedDict in it against the output layout once and computes
+//! each output entry in a single pass, with no intermediate buffers.
+//!
+//! The output has the keys of the RedDict the expression started from, and
+//! every binary step behaves like the eager method with `how="left"`: keys
+//! the right operand lacks take `fill` (or become null when `fill` is
+//! `None`), and a null on either side gives a null. With `strict`, a step
+//! whose operands have different key sets raises `KeyMismatchError` instead.
+use std::sync::Arc;
+
+use pyo3::prelude::*;
+
+use crate::align::{align, Alignment};
+use crate::bitmap::Bitmap;
+use crate::{check_keys, ops, options, RedDict};
+
+type BinaryFn = fn(f64, f64) -> f64;
+type UnaryFn = fn(f64) -> f64;
+
+enum Node {
+    Dict(RedDict),
+    Scalar(f64),
+    Binary {
+        f: BinaryFn,
+        fill: Option<f64>,
+        left: Arc<Node>,
+        right: Arc<Node>,
+    },
+    Unary {
+        f: UnaryFn,
+        arg: Arc<Node>,
+    },
+}
+
+impl Node {
+    /// The RedDict whose keys an expression produces: its leftmost leaf.
+    fn layout(&self) -> Option<&RedDict> {
+        match self {
+            Node::Dict(d) => Some(d),
+            Node::Scalar(_) => None,
+            Node::Binary { left, .. } => left.layout(),
+            Node::Unary { arg, .. } => arg.layout(),
+        }
+    }
+
+    /// Raises `KeyMismatchError` for the first binary step, innermost
+    /// first, whose two operands have different key sets.
+    fn check_keys(&self, py: Python<'_>) -> PyResult<()> {
+        match self {
+            Node::Dict(_) | Node::Scalar(_) => Ok(()),
+            Node::Binary { left, right, .. } => {
+                left.check_keys(py)?;
+                right.check_keys(py)?;
+                match (left.layout(), right.layout()) {
+                    (Some(left), Some(right)) => check_keys(py, left, right, true),
+                    _ => Ok(()),
+                }
+            }
+            Node::Unary { arg, .. } => arg.check_keys(py),
+        }
+    }
+}
+
+/// A lazily evaluated chain of operations, started with `rb.expr(d)`.
+///
+/// Nothing is computed until `collect()`, which evaluates the whole chain
+/// in one pass over the output. The result has the keys of `d`, and binary
+/// steps align like the eager methods with `how="left"`.
+///
+/// # Examples
+///
+/// ```python
+/// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
+/// >>> rb.expr(d).add_scalar(4).add(d).multiply(d).collect().to_dict
+/// {'a': 6.0, 'b': 16.0}
+/// ```
+#[pyclass(frozen, skip_from_py_object)]
+pub(crate) struct Expr {
+    node: Arc<Node>,
+}
+
+/// The right-hand side of a binary expression step.
+#[derive(FromPyObject)]
+enum ExprOperand<'py> {
+    Dict(Bound<'py, RedDict>),
+    Expr(Bound<'py, Expr>),
+}
+
+impl ExprOperand<'_> {
+    fn node(&self) -> Arc<Node> {
+        match self {
+            ExprOperand::Dict(d) => Arc::new(Node::Dict(d.borrow().clone())),
+            ExprOperand::Expr(e) => Arc::clone(&e.get().node),
+        }
+    }
+}
+
+/// Starts a lazy expression from a RedDict.
+#[pyfunction]
+pub(crate) fn expr(dict: &Bound<RedDict>) -> Expr {
+    Expr {
+        node: Arc::new(Node::Dict(dict.borrow().clone())),
+    }
+}
+
+#[pymethods]
+impl Expr {
+    /// Adds another RedDict or expression; see `RedDict.add`.
+    #[pyo3(signature = (other, fill=Some(0.0)))]
+    fn add(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, |a, b| a + b)
+    }
+
+    /// Subtracts another RedDict or expression; see `RedDict.subtract`.
+    #[pyo3(signature = (other, fill=Some(0.0)))]
+    fn subtract(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, |a, b| a - b)
+    }
+
+    /// Multiplies by another RedDict or expression; see `RedDict.multiply`.
+    #[pyo3(signature = (other, fill=Some(1.0)))]
+    fn multiply(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, |a, b| a * b)
+    }
+
+    /// Divides by another RedDict or expression; see `RedDict.divide`.
+    #[pyo3(signature = (other, fill=Some(1.0)))]
+    fn divide(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, |a, b| a / b)
+    }
+
+    /// Element-wise maximum; see `RedDict.maximum`.
+    #[pyo3(signature = (other, fill=Some(f64::NEG_INFINITY)))]
+    fn maximum(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, ops::maximum)
+    }
+
+    /// Element-wise minimum; see `RedDict.minimum`.
+    #[pyo3(signature = (other, fill=Some(f64::INFINITY)))]
+    fn minimum(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, ops::minimum)
+    }
+
+    /// Element-wise power; see `RedDict.power`.
+    #[pyo3(signature = (other, fill=Some(1.0)))]
+    fn power(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, f64::powf)
+    }
+
+    /// Element-wise Python-style remainder; see `RedDict.modulo`.
+    #[pyo3(signature = (other, fill=None))]
+    fn modulo(&self, other: ExprOperand, fill: Option<f64>) -> Self {
+        self.binary(other.node(), fill, ops::py_mod)
+    }
+
+    fn add_scalar(&self, value: f64) -> Self {
+        self.scalar(value, |a, b| a + b)
+    }
+
+    fn subtract_scalar(&self, value: f64) -> Self {
+        self.scalar(value, |a, b| a - b)
+    }
+
+    fn multiply_scalar(&self, value: f64) -> Self {
+        self.scalar(value, |a, b| a * b)
+    }
+
+    fn divide_scalar(&self, value: f64) -> Self {
+        self.scalar(value, |a, b| a / b)
+    }
+
+    fn power_scalar(&self, value: f64) -> Self {
+        self.scalar(value, f64::powf)
+    }
+
+    fn neg(&self) -> Self {
+        self.unary(|v| -v)
+    }
+
+    fn abs(&self) -> Self {
+        self.unary(f64::abs)
+    }
+
+    fn sqrt(&self) -> Self {
+        self.unary(f64::sqrt)
+    }
+
+    /// Evaluates the expression into a new RedDict.
+    ///
+    /// With `strict=True` (or the module default set by `set_options`), a
+    /// binary step whose operands have different key sets raises
+    /// `KeyMismatchError`, as the eager methods do.
+    #[pyo3(name = "collect", signature = (strict=None))]
+    fn py_collect(&self, py: Python<'_>, strict: Option<bool>) -> PyResult<RedDict> {
+        if options::resolve_strict(strict) {
+            self.node.check_keys(py)?;
+        }
+        Ok(self.collect())
+    }
+}
+
+impl Expr {
+    fn collect(&self) -> RedDict {
+        Program::compile(&self.node).run()
+    }
+
+    fn binary(&self, right: Arc<Node>, fill: Option<f64>, f: BinaryFn) -> Self {
+        Expr {
+            node: Arc::new(Node::Binary {
+                f,
+                fill,
+                left: Arc::clone(&self.node),
+                right,
+            }),
+        }
+    }
+
+    fn scalar(&self, value: f64, f: BinaryFn) -> Self {
+        self.binary(Arc::new(Node::Scalar(value)), None, f)
+    }
+
+    fn unary(&self, f: UnaryFn) -> Self {
+        Expr {
+            node: Arc::new(Node::Unary {
+                f,
+                arg: Arc::clone(&self.node),
+            }),
+        }
+    }
+}
+
+/// One step of a compiled expression, run on a small stack per entry.
+enum Instr {
+    /// Pushes the current entry of a leaf, looked up through its alignment.
+    Load(usize),
+    Const(f64),
+    Binary(BinaryFn, Option<f64>),
+    Unary(UnaryFn),
+}
+
+/// The value of a sub-expression at one output position.
+#[derive(Clone, Copy)]
+enum Slot {
+    Value(f64),
+    Null,
+    /// The key is not in the sub-expression's layout.
+    Missing,
+}
+
+/// An expression flattened into postfix order, with every leaf aligned
+/// against the output layout.
+struct Program<'a> {
+    output: &'a RedDict,
+    leaves: Vec<(&'a RedDict, Alignment)>,
+    instrs: Vec<Instr>,
+    depth: usize,
+}
+
+impl<'a> Program<'a> {
+    fn compile(node: &'a Node) -> Self {
+        let output = node
+            .layout()
+            .expect("expressions always start from a RedDict");
+        let mut program = Program {
+            output,
+            leaves: Vec::new(),
+            instrs: Vec::new(),
+            depth: 0,
+        };
+        program.depth = program.push(node);
+        program
+    }
+
+    /// Appends `node` in postfix order, returning the stack depth it needs.
+    fn push(&mut self, node: &'a Node) -> usize {
+        match node {
+            Node::Dict(d) => {
+                let alignment = align(&self.output.index, &d.index);
+                self.instrs.push(Instr::Load(self.leaves.len()));
+                self.leaves.push((d, alignment));
+                1
+            }
+            Node::Scalar(value) => {
+                self.instrs.push(Instr::Const(*value));
+                1
+            }
+            Node::Binary {
+                f,
+                fill,
+                left,
+                right,
+            } => {
+                let left = self.push(left);
+                let right = self.push(right);
+                self.instrs.push(Instr::Binary(*f, *fill));
+                left.max(right + 1)
+            }
+            Node::Unary { f, arg } => {
+                let depth = self.push(arg);
+                self.instrs.push(Instr::Unary(*f));
+                depth
+            }
+        }
+    }
+
+    fn load(&self, leaf: usize, i: usize) -> Slot {
+        let (dict, alignment) = &self.leaves[leaf];
+        let j = match alignment {
+            Alignment::Identical => i,
+            Alignment::Gather(gather) => match gather[i] {
+                Some(j) => j,
+                None => return Slot::Missing,
+            },
+        };
+        dict.value_at(j).map_or(Slot::Null, Slot::Value)
+    }
+
+    fn run(&self) -> RedDict {
+        let len = self.output.values.len();
+        let mut values = Vec::with_capacity(len);
+        let mut validity: Option<Bitmap> = None;
+        let mut stack = Vec::with_capacity(self.depth);
+
+        for i in 0..len {
+            stack.clear();
+            for instr in &self.instrs {
+                let slot = match *instr {
+                    Instr::Load(leaf) => self.load(leaf, i),
+                    Instr::Const(value) => Slot::Value(value),
+                    Instr::Unary(f) => match stack.pop() {
+                        Some(Slot::Value(v)) => Slot::Value(f(v)),
+                        Some(other) => other,
+                        None => unreachable!("compiled program underflowed"),
+                    },
+                    Instr::Binary(f, fill) => {
+                        let right = stack.pop().expect("compiled program underflowed");
+                        let left = stack.pop().expect("compiled program underflowed");
+                        let right = match (right, fill) {
+                            (Slot::Missing, Some(fill)) => Slot::Value(fill),
+                            (Slot::Missing, None) => Slot::Null,
+                            (right, _) => right,
+                        };
+                        match (left, right) {
+                            (Slot::Value(a), Slot::Value(b)) => Slot::Value(f(a, b)),
+                            (Slot::Missing, _) => Slot::Missing,
+                            _ => Slot::Null,
+                        }
+                    }
+                };
+                stack.push(slot);
+            }
+
+            match stack.pop() {
+                Some(Slot::Value(v)) => values.push(v),
+                _ => {
+                    values.push(f64::NAN);
+                    validity
+                        .get_or_insert_with(|| Bitmap::new_valid(len))
+                        .set(i, false);
+                }
+            }
+        }
+
+        RedDict {
+            keys: Arc::clone(&self.output.keys),
+            index: Arc::clone(&self.output.index),
+            values: Arc::new(values),
+            validity: validity.map(Arc::new),
+            frozen: self.output.frozen,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyMismatchError;
+    use pyo3::types::PyDict;
+
+    fn make_dict<'py>(py: Python<'py>, entries: &[(&str, Option<f64>)]) -> Bound<'py, RedDict> {
+        Bound::new(py, crate::tests::make_dict(py, entries)).unwrap()
+    }
+
+    /// Evaluates `code` with `expr` and the RedDicts `d1`, `d2` and `d3` in
+    /// scope, as a script using `rb.expr` would.
+    fn run_python<'py>(
+        py: Python<'py>,
+        dicts: [&Bound<'py, RedDict>; 3],
+        code: &std::ffi::CStr,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let locals = PyDict::new(py);
+        locals.set_item("expr", wrap_pyfunction!(expr, py)?)?;
+        for (name, dict) in ["d1", "d2", "d3"].into_iter().zip(dicts) {
+            locals.set_item(name, dict)?;
+        }
+        py.eval(code, None, Some(&locals))
+    }
+
+    fn operand<'py>(d: &Bound<'py, RedDict>) -> ExprOperand<'py> {
+        ExprOperand::Dict(d.clone())
+    }
+
+    #[test]
+    fn test_fused_chain_matches_eager_ops() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d = make_dict(py, &[("a", Some(1.0)), ("b", Some(2.0))]);
+            let result = expr(&d)
+                .add_scalar(4.0)
+                .add(operand(&d), Some(0.0))
+                .subtract_scalar(4.0)
+                .subtract(operand(&d), Some(0.0))
+                .multiply(operand(&d), Some(1.0))
+                .collect();
+            assert_eq!(*result.values, [1.0, 4.0]);
+
+            let result = expr(&d)
+                .add_scalar(4.0)
+                .add(operand(&d), Some(0.0))
+                .multiply(operand(&d), Some(1.0))
+                .collect();
+            assert_eq!(*result.values, [6.0, 16.0]);
+            assert!(Arc::ptr_eq(&result.index, &d.borrow().index));
+            assert!(result.validity.is_none());
+        });
+    }
+
+    #[test]
+    fn test_operands_are_aligned_to_the_first_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0)), ("b", Some(2.0)), ("c", Some(3.0))]);
+            let d2 = make_dict(
+                py,
+                &[("c", Some(30.0)), ("a", Some(10.0)), ("z", Some(99.0))],
+            );
+            let result = expr(&d1).add(operand(&d2), Some(0.0)).collect();
+            assert_eq!(result.keys(), ["a", "b", "c"]);
+            assert_eq!(*result.values, [11.0, 2.0, 33.0]);
+
+            let result = expr(&d1).divide(operand(&d2), None).collect();
+            assert_eq!(result.py_values(), [Some(0.1), None, Some(0.1)]);
+        });
+    }
+
+    #[test]
+    fn test_nested_expressions_use_their_own_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0)), ("b", Some(2.0))]);
+            let d2 = make_dict(py, &[("a", Some(10.0))]);
+            let d3 = make_dict(py, &[("a", Some(100.0)), ("b", Some(200.0))]);
+            // `b` is missing from `d2 + d3`, whose keys are those of d2, so it
+            // takes the outer fill rather than d3's value.
+            let inner = Bound::new(py, expr(&d2).add(operand(&d3), Some(0.0))).unwrap();
+            let result = expr(&d1)
+                .multiply(ExprOperand::Expr(inner), Some(-1.0))
+                .collect();
+            assert_eq!(*result.values, [110.0, -2.0]);
+        });
+    }
+
+    #[test]
+    fn test_nulls_propagate_through_the_chain() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(4.0)), ("b", None)]);
+            let d2 = make_dict(py, &[("a", Some(1.0)), ("b", Some(1.0))]);
+            let result = expr(&d2)
+                .add(operand(&d1), Some(0.0))
+                .sqrt()
+                .neg()
+                .collect();
+            assert_eq!(result.py_values(), [Some(-(5.0_f64).sqrt()), None]);
+            assert!(result.values[1].is_nan());
+        });
+    }
+
+    #[test]
+    fn test_collect_from_python() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0)), ("b", Some(2.0))]);
+            let d2 = make_dict(py, &[("b", Some(20.0)), ("a", Some(10.0))]);
+            let d3 = make_dict(py, &[("a", Some(100.0))]);
+            let result = run_python(
+                py,
+                [&d1, &d2, &d3],
+                c"list(expr(d1).add_scalar(1.0).multiply(d2).add(d3).collect().to_dict.items())",
+            )
+            .unwrap();
+            let result: Vec<(String, f64)> = result.extract().unwrap();
+            assert_eq!(result, [("a".to_string(), 120.0), ("b".to_string(), 60.0)]);
+        });
+    }
+
+    #[test]
+    fn test_strict_collect_checks_every_step() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0)), ("b", Some(2.0))]);
+            let d2 = make_dict(py, &[("b", Some(20.0)), ("a", Some(10.0))]);
+            let d3 = make_dict(py, &[("a", Some(100.0))]);
+            let dicts = [&d1, &d2, &d3];
+
+            let same_keys = c"expr(d1).add(d2).multiply_scalar(2.0).collect(strict=True)";
+            assert!(run_python(py, dicts, same_keys).is_ok());
+            for code in [
+                c"expr(d1).add(d2).subtract(d3).collect(strict=True)",
+                c"expr(d1).multiply(expr(d3).add(d1)).collect(strict=True)",
+            ] {
+                let err = run_python(py, dicts, code).unwrap_err();
+                assert!(err.is_instance_of::<KeyMismatchError>(py), "{code:?}");
+            }
+            let code = c"expr(d1).subtract(d3).collect(strict=False)";
+            assert!(run_python(py, dicts, code).is_ok());
+        });
+    }
+}
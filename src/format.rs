@@ -0,0 +1,381 @@
+//! The redbear binary format, behind `to_bytes` / `from_bytes`, `save` /
+//! `load` and their `_many` counterparts.
+//!
+//! One blob holds any number of RedDicts over a single shared key table.
+//! All integers are little-endian.
+//!
+//! ```text
+//! offset  size  field
+//! 0       4     magic, b"RBDT"
+//! 4       2     format version (u16), currently 1
+//! 6       2     flags (u16); bit 0 set when a checksum trailer is present
+//! 8       4     number of keys, n (u32)
+//! 12      4     number of dicts, m (u32)
+//! 16            key table: n times a u32 byte length then that many bytes
+//!               of UTF-8
+//!               m dict blocks, each:
+//!                 1 byte of flags; bit 0 frozen, bit 1 has a validity mask
+//!                 the validity mask, ceil(n / 8) bytes, bit i of byte i / 8
+//!                 (least significant first) set when entry i is not null;
+//!                 only present when flagged
+//!                 n values as f64; null entries hold NaN
+//! end-4   4     CRC-32 (IEEE) of every preceding byte, when flagged
+//! ```
+//!
+//! Values follow the key table's order. When dicts written together have
+//! their keys in different orders, the later ones are reordered to match the
+//! first; they must all have the same key set. Dicts read from one blob
+//! share a single interned key layout.
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyBytes};
+
+use crate::{
+    align::{align, Alignment},
+    bitmap::Bitmap,
+    check_keys,
+    schema::KeySchema,
+    RedDict,
+};
+
+const MAGIC: &[u8; 4] = b"RBDT";
+const VERSION: u16 = 1;
+const HEADER_LEN: usize = 16;
+
+const HAS_CHECKSUM: u16 = 1;
+const FROZEN: u8 = 1;
+const HAS_VALIDITY: u8 = 2;
+
+/// Serializes `dicts` over one shared key table.
+pub(crate) fn encode(py: Python<'_>, dicts: &[RedDict], checksum: bool) -> PyResult<Vec<u8>> {
+    let keys: &[String] = dicts.first().map_or(&[], |first| &first.keys);
+    let n = keys.len();
+    let mut out = Vec::with_capacity(HEADER_LEN + n * 8 * (dicts.len() + 2));
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    let flags = if checksum { HAS_CHECKSUM } else { 0 };
+    out.extend_from_slice(&flags.to_le_bytes());
+    out.extend_from_slice(&to_u32(n, "keys")?.to_le_bytes());
+    out.extend_from_slice(&to_u32(dicts.len(), "dicts")?.to_le_bytes());
+    for key in keys {
+        out.extend_from_slice(&to_u32(key.len(), "bytes in a key")?.to_le_bytes());
+        out.extend_from_slice(key.as_bytes());
+    }
+
+    for dict in dicts {
+        // Positions of the first dict's keys in this one.
+        let order = match align(&dicts[0].index, &dict.index) {
+            Alignment::Identical => None,
+            Alignment::Gather(order) => {
+                check_keys(py, &dicts[0], dict, true)?;
+                Some(order)
+            }
+        };
+        let source = |i: usize| order.as_ref().map_or(i, |order| order[i].unwrap_or(i));
+
+        let mut flags = if dict.frozen { FROZEN } else { 0 };
+        if dict.validity.is_some() {
+            flags |= HAS_VALIDITY;
+        }
+        out.push(flags);
+        if let Some(validity) = &dict.validity {
+            match &order {
+                None => out.extend_from_slice(&validity.to_bytes()),
+                Some(_) => {
+                    let reordered = Bitmap::from_fn(n, |i| validity.get(source(i)));
+                    out.extend_from_slice(&reordered.to_bytes());
+                }
+            }
+        }
+        for i in 0..n {
+            out.extend_from_slice(&dict.values[source(i)].to_le_bytes());
+        }
+    }
+
+    if checksum {
+        let crc = crc32(&out);
+        out.extend_from_slice(&crc.to_le_bytes());
+    }
+    Ok(out)
+}
+
+/// Parses a blob written by `encode`.
+pub(crate) fn decode(data: &[u8]) -> PyResult<Vec<RedDict>> {
+    if data.len() < HEADER_LEN || &data[..4] != MAGIC {
+        return Err(PyValueError::new_err("not a redbear binary blob"));
+    }
+    let mut header = Reader {
+        data: &data[4..HEADER_LEN],
+    };
+    let version = header.u16()?;
+    if version != VERSION {
+        return Err(PyValueError::new_err(format!(
+            "unsupported redbear format version {version}"
+        )));
+    }
+    let flags = header.u16()?;
+    let n = header.u32()? as usize;
+    let m = header.u32()? as usize;
+
+    let mut body = &data[HEADER_LEN..];
+    if flags & HAS_CHECKSUM != 0 {
+        let Some(split) = data.len().checked_sub(4).filter(|&s| s >= HEADER_LEN) else {
+            return Err(truncated());
+        };
+        let stored = u32::from_le_bytes(data[split..].try_into().expect("4 bytes"));
+        if crc32(&data[..split]) != stored {
+            return Err(PyValueError::new_err("checksum mismatch"));
+        }
+        body = &data[HEADER_LEN..split];
+    }
+    let mut reader = Reader { data: body };
+
+    // Every key takes at least its 4-byte length, so a count larger than
+    // that is corrupt; checking first avoids a huge allocation.
+    if n > reader.data.len() / 4 {
+        return Err(truncated());
+    }
+    let mut keys = Vec::with_capacity(n);
+    for _ in 0..n {
+        let len = reader.u32()? as usize;
+        let key = std::str::from_utf8(reader.take(len)?)
+            .map_err(|_| PyValueError::new_err("key is not valid UTF-8"))?;
+        keys.push(key.to_string());
+    }
+    let schema = KeySchema::new(keys)?;
+
+    let mut dicts = Vec::with_capacity(m.min(reader.data.len()));
+    for _ in 0..m {
+        let flags = reader.take(1)?[0];
+        let validity = if flags & HAS_VALIDITY != 0 {
+            Bitmap::from_bytes(reader.take(n.div_ceil(8))?, n).ok_or_else(truncated)?
+        } else {
+            Bitmap::new_valid(n)
+        };
+        let values = reader
+            .take(n.checked_mul(8).ok_or_else(truncated)?)?
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes")))
+            .collect();
+        dicts.push(
+            RedDict {
+                keys: Arc::clone(&schema.keys),
+                index: Arc::clone(&schema.index),
+                values: Arc::new(values),
+                validity: None,
+                frozen: flags & FROZEN != 0,
+            }
+            .with_validity(validity),
+        );
+    }
+    if !reader.data.is_empty() {
+        return Err(PyValueError::new_err(
+            "unexpected bytes after the last dict",
+        ));
+    }
+    Ok(dicts)
+}
+
+/// Consumes a byte slice front to back.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> PyResult<&'a [u8]> {
+        if len > self.data.len() {
+            return Err(truncated());
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    fn u16(&mut self) -> PyResult<u16> {
+        Ok(u16::from_le_bytes(
+            self.take(2)?.try_into().expect("2 bytes"),
+        ))
+    }
+
+    fn u32(&mut self) -> PyResult<u32> {
+        Ok(u32::from_le_bytes(
+            self.take(4)?.try_into().expect("4 bytes"),
+        ))
+    }
+}
+
+fn truncated() -> PyErr {
+    PyValueError::new_err("redbear binary blob is truncated")
+}
+
+fn to_u32(len: usize, what: &str) -> PyResult<u32> {
+    u32::try_from(len)
+        .map_err(|_| PyValueError::new_err(format!("too many {what} for the binary format")))
+}
+
+/// CRC-32 with the IEEE polynomial, as used by zlib and PNG.
+fn crc32(data: &[u8]) -> u32 {
+    static TABLE: std::sync::LazyLock<[u32; 256]> = std::sync::LazyLock::new(|| {
+        let mut table = [0; 256];
+        for (n, entry) in (0u32..).zip(table.iter_mut()) {
+            *entry = (0..8).fold(n, |c, _| {
+                if c & 1 != 0 {
+                    0xEDB8_8320 ^ (c >> 1)
+                } else {
+                    c >> 1
+                }
+            });
+        }
+        table
+    });
+    !data.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ u32::from(byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// Serializes several RedDicts into one blob that stores their keys once.
+///
+/// Every dict must have the same keys as the first; values of dicts whose
+/// keys are in another order are reordered to the first dict's order.
+///
+/// # Examples
+///
+/// ```python
+/// >>> d1 = rb.RedDict({"a": 1.0, "b": 2.0})
+/// >>> d2 = rb.RedDict({"b": 20.0, "a": 10.0})
+/// >>> [d.to_dict for d in rb.from_bytes_many(rb.to_bytes_many([d1, d2]))]
+/// [{'a': 1.0, 'b': 2.0}, {'a': 10.0, 'b': 20.0}]
+/// ```
+#[pyfunction]
+#[pyo3(signature = (dicts, checksum=true))]
+pub(crate) fn to_bytes_many<'py>(
+    py: Python<'py>,
+    dicts: Vec<PyRef<RedDict>>,
+    checksum: bool,
+) -> PyResult<Bound<'py, PyBytes>> {
+    let dicts: Vec<RedDict> = dicts.iter().map(|d| (**d).clone()).collect();
+    Ok(PyBytes::new(py, &encode(py, &dicts, checksum)?))
+}
+
+/// Reads every RedDict from a blob written by `to_bytes_many` (or
+/// `RedDict.to_bytes`). They all share one key layout.
+#[pyfunction]
+pub(crate) fn from_bytes_many(data: &[u8]) -> PyResult<Vec<RedDict>> {
+    decode(data)
+}
+
+/// Writes `to_bytes_many(dicts, checksum)` to the file at `path`.
+#[pyfunction]
+#[pyo3(signature = (path, dicts, checksum=true))]
+pub(crate) fn save_many(
+    py: Python<'_>,
+    path: PathBuf,
+    dicts: Vec<PyRef<RedDict>>,
+    checksum: bool,
+) -> PyResult<()> {
+    let dicts: Vec<RedDict> = dicts.iter().map(|d| (**d).clone()).collect();
+    std::fs::write(path, encode(py, &dicts, checksum)?)?;
+    Ok(())
+}
+
+/// Reads every RedDict from a file written by `save_many` or `RedDict.save`.
+#[pyfunction]
+pub(crate) fn load_many(path: PathBuf) -> PyResult<Vec<RedDict>> {
+    decode(&std::fs::read(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::make_dict;
+
+    #[test]
+    fn test_crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_layout_matches_documentation() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("é", Some(1.0)), ("b", None)]);
+            let bytes = encode(py, &[rd], false).unwrap();
+            let mut expected = b"RBDT\x01\x00\x00\x00\x02\x00\x00\x00\x01\x00\x00\x00".to_vec();
+            expected.extend_from_slice(b"\x02\x00\x00\x00\xc3\xa9\x01\x00\x00\x00b");
+            expected.extend_from_slice(&[HAS_VALIDITY, 0b01]);
+            expected.extend_from_slice(&1.0f64.to_le_bytes());
+            expected.extend_from_slice(&f64::NAN.to_le_bytes());
+            assert_eq!(bytes, expected);
+        });
+    }
+
+    #[test]
+    fn test_round_trip_keeps_values_nulls_and_frozen() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(
+                py,
+                &[("a", Some(-0.0)), ("b", None), ("c", Some(f64::INFINITY))],
+            );
+            let frozen = make_dict(py, &[("c", Some(3.0)), ("a", Some(1.0)), ("b", Some(2.0))]);
+            let dicts = [rd.clone(), frozen.freeze()];
+            for checksum in [false, true] {
+                let decoded = decode(&encode(py, &dicts, checksum).unwrap()).unwrap();
+                assert_eq!(decoded.len(), 2);
+                assert_eq!(decoded[0].py_to_dict(), rd.py_to_dict());
+                assert!(decoded[0].values[0].is_sign_negative());
+                assert!(!decoded[0].frozen);
+                assert_eq!(*decoded[1].keys, ["a", "b", "c"]);
+                assert_eq!(*decoded[1].values, [1.0, 2.0, 3.0]);
+                assert!(decoded[1].validity.is_none() && decoded[1].frozen);
+                assert!(Arc::ptr_eq(&decoded[0].index, &decoded[1].index));
+            }
+            assert!(decode(&encode(py, &[], true).unwrap()).unwrap().is_empty());
+        });
+    }
+
+    #[test]
+    fn test_mismatched_keys_are_rejected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0))]);
+            let d2 = make_dict(py, &[("b", Some(1.0))]);
+            let err = encode(py, &[d1, d2], true).unwrap_err();
+            assert!(err.is_instance_of::<crate::KeyMismatchError>(py));
+        });
+    }
+
+    #[test]
+    fn test_corrupt_blobs_are_rejected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(1.0)), ("b", None)]);
+            let bytes = encode(py, &[rd], true).unwrap();
+            for len in 0..bytes.len() {
+                assert!(decode(&bytes[..len]).is_err(), "prefix of {len} bytes");
+            }
+            let mut flipped = bytes.clone();
+            flipped[20] ^= 1;
+            let err = decode(&flipped).err().unwrap().to_string();
+            assert!(err.contains("checksum"), "{err}");
+
+            let mut version = bytes.clone();
+            version[4] = 9;
+            assert!(decode(&version)
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("version"));
+
+            let unchecked = encode(py, &[make_dict(py, &[("a", Some(1.0))])], false).unwrap();
+            let mut extra = unchecked.clone();
+            extra.push(0);
+            assert!(decode(&extra).is_err());
+            let mut huge = unchecked;
+            huge[8..12].copy_from_slice(&u32::MAX.to_le_bytes());
+            assert!(decode(&huge).is_err());
+        });
+    }
+}
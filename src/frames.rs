@@ -0,0 +1,113 @@
+//! pandas and polars conversions.
+//!
+//! Values cross over as buffers: numpy arrays for pandas and the Arrow
+//! stream export for polars. Duplicate and non-string labels are checked
+//! with the libraries' own vectorized methods before any key is read.
+use std::sync::Arc;
+
+use pyo3::{
+    buffer::PyBuffer,
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::PyDict,
+};
+
+use crate::{array, arrow, bitmap::Bitmap, schema::KeySchema, RedDict};
+
+/// A Series of the values (nulls as NaN) indexed by key, in key order.
+pub(crate) fn to_pandas<'py>(dict: &Bound<'py, RedDict>) -> PyResult<Bound<'py, PyAny>> {
+    let py = dict.py();
+    let pandas = py.import("pandas")?;
+    let values = RedDict::__array__(dict, None, Some(true))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", "object")?;
+    let index = pandas.call_method("Index", (dict.borrow().keys(),), Some(&kwargs))?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("index", index)?;
+    kwargs.set_item("copy", false)?;
+    pandas.call_method("Series", (values,), Some(&kwargs))
+}
+
+/// A RedDict from a Series with unique string labels. Missing values
+/// (NaN, `None`, `pd.NA`) become nulls.
+pub(crate) fn from_pandas(series: &Bound<PyAny>) -> PyResult<RedDict> {
+    let py = series.py();
+    let index = series.getattr("index")?;
+    let inferred: String = index.getattr("inferred_type")?.extract()?;
+    if inferred != "string" && inferred != "empty" {
+        return Err(PyTypeError::new_err(format!(
+            "index labels must be strings, got {inferred} labels"
+        )));
+    }
+    if !index.getattr("is_unique")?.extract::<bool>()? {
+        let duplicates = index.get_item(index.call_method0("duplicated")?)?;
+        return Err(PyValueError::new_err(format!(
+            "duplicate index label {}",
+            duplicates.get_item(0)?.repr()?
+        )));
+    }
+
+    let keys = array::extract_keys(&index.call_method0("to_numpy")?)?;
+    let kwargs = PyDict::new(py);
+    kwargs.set_item("dtype", "float64")?;
+    kwargs.set_item("na_value", f64::NAN)?;
+    let (values, _) = array::extract_values(&series.call_method("to_numpy", (), Some(&kwargs))?)?;
+    let missing = series
+        .call_method0("isna")?
+        .call_method1("to_numpy", ("uint8",))?;
+    let missing = PyBuffer::<u8>::get(&missing)?.to_vec(py)?;
+
+    let schema = KeySchema::new(keys)?;
+    Ok(RedDict {
+        keys: schema.keys,
+        index: schema.index,
+        values: Arc::new(values),
+        validity: None,
+        frozen: false,
+    }
+    .with_validity(Bitmap::from_fn(missing.len(), |i| missing[i] == 0)))
+}
+
+/// A DataFrame with `key` and `value` columns, in key order.
+pub(crate) fn to_polars<'py>(dict: &Bound<'py, RedDict>) -> PyResult<Bound<'py, PyAny>> {
+    dict.py()
+        .import("polars")?
+        .call_method1("DataFrame", (dict,))
+}
+
+/// A RedDict from two columns of a DataFrame: unique, non-null strings in
+/// `key_col` and numbers in `value_col`, which is cast to Float64.
+pub(crate) fn from_polars(df: &Bound<PyAny>, key_col: &str, value_col: &str) -> PyResult<RedDict> {
+    let py = df.py();
+    let polars = py.import("polars")?;
+    let key = df.call_method1("get_column", (key_col,))?;
+    if !key.getattr("dtype")?.eq(polars.getattr("String")?)? {
+        return Err(PyTypeError::new_err(format!(
+            "key column {key_col:?} must be String, got {}",
+            key.getattr("dtype")?.str()?
+        )));
+    }
+    if key.call_method0("null_count")?.extract::<usize>()? > 0 {
+        return Err(PyValueError::new_err(format!(
+            "key column {key_col:?} contains nulls"
+        )));
+    }
+    let duplicated = key.call_method0("is_duplicated")?;
+    if duplicated.call_method0("any")?.extract::<bool>()? {
+        let duplicates = key.call_method1("filter", (duplicated,))?;
+        return Err(PyValueError::new_err(format!(
+            "duplicate key {}",
+            duplicates.get_item(0)?.repr()?
+        )));
+    }
+
+    // Renamed so `from_arrow` finds the columns whatever they were called.
+    let value = df
+        .call_method1("get_column", (value_col,))?
+        .call_method1("cast", (polars.getattr("Float64")?,))?;
+    let columns = [
+        key.call_method1("alias", ("key",))?,
+        value.call_method1("alias", ("value",))?,
+    ];
+    arrow::from_arrow(&polars.call_method1("DataFrame", (columns.to_vec(),))?)
+}
@@ -3,29 +3,95 @@
 //! # Architecture
 //!
 //! Keys and values are stored in separate parallel arrays for cache efficiency.
-//! An index maps keys to their positions in the values array.
+//! An index maps keys to their positions in the values array. Keys keep the
+//! insertion order of the source dict, which is the order used by `to_dict`,
+//! iteration and every other ordered view.
+//!
+//! # Missing values
+//!
+//! Entries can be null (shown as `None` in Python), which is distinct from a
+//! NaN value. Nulls are tracked by an optional validity bitmap; dicts without
+//! nulls carry no bitmap at all, so the common case pays nothing. Null slots
+//! in `values` always hold NaN.
 //!
 //! # Immutability
 //!
 //! All operations return new instances. Internal data uses `Arc` for cheap cloning
-//! with copy-on-write semantics via `Arc::make_mut`.
+//! with copy-on-write semantics via `Arc::make_mut`. Long chains of operations
+//! can skip the intermediate instances entirely through the lazy `expr` API.
+//!
+//! The exceptions are the in-place methods (`iadd`, `iadd_scalar`, ...),
+//! augmented assignments (`+=`, ...) and the mutable mapping methods
+//! (`__setitem__`, `__delitem__`, `update`, `pop`, `setdefault`, `clear`),
+//! which write into the existing storage when the RedDict owns it alone.
+//! Storage shared with another RedDict or a `KeySchema` is copied before the
+//! write, so a mutation is never visible through anything else. Deleting a
+//! key shifts the keys after it down one position, keeping the index dense.
+//!
+//! `freeze` returns a RedDict that refuses all of these mutations and, in
+//! exchange, is hashable, so it can be used as a dict key or set member.
 use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::os::raw::c_int;
+use std::path::PathBuf;
 use std::sync::Arc;
 
-use pyo3::{prelude::*, types::PyDict};
+use indexmap::IndexMap;
+use pyo3::{
+    create_exception,
+    exceptions::{PyKeyError, PyTypeError, PyValueError},
+    ffi,
+    impl_::pymethods::BoundRef,
+    prelude::*,
+    types::{PyBytes, PyCapsule, PyDict, PyString, PyTuple},
+};
 
-#[pyclass]
+mod align;
+mod array;
+mod arrow;
+mod bitmap;
+mod build;
+mod display;
+mod expr;
+mod format;
+mod frames;
+mod ops;
+mod options;
+mod schema;
+mod stats;
+mod ufunc;
+
+use align::{align, Alignment};
+use bitmap::Bitmap;
+use build::{Builder, OnDuplicate};
+use schema::KeySchema;
+use stats::Summation;
+
+#[pyclass(module = "redbear", skip_from_py_object)]
 #[derive(Clone)]
 struct RedDict {
+    /// Keys in insertion order.
+    keys: Arc<Vec<String>>,
     /// Mapping from key -> index into `values`.
     index: Arc<HashMap<String, usize>>,
     /// Packed numeric values, aligned with `keys`.
     values: Arc<Vec<f64>>,
+    /// Which entries of `values` are present; `None` when none are null.
+    validity: Option<Arc<Bitmap>>,
+    /// Set by `freeze`: mutation is refused and the dict is hashable.
+    frozen: bool,
 }
 
 #[pymethods]
 impl RedDict {
-    /// Creates a new `RedDict` from a Python dictionary.
+    /// Creates a new `RedDict` from a Python dictionary and/or keyword
+    /// arguments, which are added after (and override) the dict's entries.
+    ///
+    /// `None` values become nulls.
+    ///
+    /// When `schema` is given, values are laid out in the schema's key order
+    /// so the result shares the fast path with every other dict built from
+    /// it. The dict's keys must then match the schema's exactly.
     ///
     /// # Examples
     ///
@@ -34,25 +100,160 @@ impl RedDict {
     /// >>> d = rb.RedDict({"x": 1.0, "y": 2.0})
     /// >>> d.to_dict
     /// {'x': 1.0, 'y': 2.0}
+    /// >>> rb.RedDict(x=1.0, y=None).to_dict
+    /// {'x': 1.0, 'y': None}
+    /// >>> s = rb.KeySchema(["y", "x"])
+    /// >>> rb.RedDict({"x": 1.0, "y": 2.0}, schema=s).to_dict
+    /// {'y': 2.0, 'x': 1.0}
     /// ```
     #[new]
-    fn new(dict: &Bound<PyDict>) -> PyResult<Self> {
-        // First extract into a temporary HashMap via PyO3, then
-        // build our split key/index/value representation.
-        let extracted: HashMap<String, f64> = dict.extract()?;
+    #[pyo3(signature = (dict=None, schema=None, **kwargs))]
+    fn py_new(
+        dict: Option<&Bound<PyDict>>,
+        schema: Option<&Bound<KeySchema>>,
+        kwargs: Option<&Bound<PyDict>>,
+    ) -> PyResult<Self> {
+        // Kwargs override the dict's entries in place, as `dict.update` would.
+        let Some(schema) = schema else {
+            let len = dict.map_or(0, |d| d.len()) + kwargs.map_or(0, |d| d.len());
+            let mut builder = Builder::with_capacity(len, OnDuplicate::Last);
+            for (k, v) in dict.into_iter().chain(kwargs).flat_map(|d| d.iter()) {
+                builder.push(k.extract()?, v.extract()?)?;
+            }
+            return Ok(builder.finish());
+        };
+        Self::with_schema(dict.into_iter().chain(kwargs), schema.get())
+    }
+
+    /// Creates a `RedDict` from an iterable of `(key, value)` pairs, keeping
+    /// keys in first-seen order.
+    ///
+    /// `on_duplicate` decides what a repeated key does: `"error"` raises
+    /// `ValueError`, `"sum"` adds the values (skipping nulls), `"last"` keeps
+    /// the latest value as `dict` would and `"first"` keeps the earliest.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict.from_items([("a", 1.0), ("b", 2.0)]).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// >>> rb.RedDict.from_items([("a", 1.0), ("b", 2.0), ("a", 3.0)], on_duplicate="sum").to_dict
+    /// {'a': 4.0, 'b': 2.0}
+    /// ```
+    #[staticmethod]
+    #[pyo3(signature = (items, on_duplicate="error"))]
+    fn from_items(items: &Bound<PyAny>, on_duplicate: &str) -> PyResult<Self> {
+        let on_duplicate = OnDuplicate::parse(on_duplicate)?;
+        let mut builder = Builder::with_capacity(items.len().unwrap_or(0), on_duplicate);
+        for (i, item) in items.try_iter()?.enumerate() {
+            let item = item?;
+            let (k, v) = match item.cast::<PyTuple>() {
+                Ok(pair) if pair.len() == 2 => (pair.get_item(0)?, pair.get_item(1)?),
+                _ => {
+                    let pair: Vec<Bound<PyAny>> = item.extract()?;
+                    let [k, v] = <[_; 2]>::try_from(pair).map_err(|pair| {
+                        PyValueError::new_err(format!(
+                            "item {i} has {} elements; expected a (key, value) pair",
+                            pair.len()
+                        ))
+                    })?;
+                    (k, v)
+                }
+            };
+            builder.push(k.extract()?, v.extract()?)?;
+        }
+        Ok(builder.finish())
+    }
 
-        let mut values = Vec::with_capacity(extracted.len());
-        let mut index = HashMap::with_capacity(extracted.len());
+    /// Creates a `RedDict` from an iterable of keys and an iterable of
+    /// values of the same length, read together in one pass. Keys must be
+    /// unique. For numpy arrays, `from_arrays` reads the buffers directly.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict.from_keys_values(["a", "b"], (1.0, None)).to_dict
+    /// {'a': 1.0, 'b': None}
+    /// ```
+    #[staticmethod]
+    fn from_keys_values(keys: &Bound<PyAny>, values: &Bound<PyAny>) -> PyResult<Self> {
+        let mut builder = Builder::with_capacity(keys.len().unwrap_or(0), OnDuplicate::Error);
+        let mut values_iter = values.try_iter()?;
+        for key in keys.try_iter()? {
+            let key = key?;
+            let Some(value) = values_iter.next() else {
+                return Err(length_mismatch(keys, values));
+            };
+            builder.push(key.extract()?, value?.extract()?)?;
+        }
+        if values_iter.next().is_some() {
+            return Err(length_mismatch(keys, values));
+        }
+        Ok(builder.finish())
+    }
 
-        for (pos, (k, v)) in extracted.into_iter().enumerate() {
-            values.push(v);
-            index.insert(k, pos);
+    /// Creates a `RedDict` mapping every key to `value` (null by default).
+    /// Repeated keys are kept once, as with `dict.fromkeys`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict.fromkeys(["a", "b", "a"], 0.0).to_dict
+    /// {'a': 0.0, 'b': 0.0}
+    /// ```
+    #[staticmethod]
+    #[pyo3(signature = (keys, value=None))]
+    fn fromkeys(keys: &Bound<PyAny>, value: Option<f64>) -> PyResult<Self> {
+        let mut builder = Builder::with_capacity(keys.len().unwrap_or(0), OnDuplicate::First);
+        for key in keys.try_iter()? {
+            builder.push(key?.extract()?, value)?;
         }
+        Ok(builder.finish())
+    }
 
+    /// Creates a `RedDict` from values already in `schema`'s key order.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> s = rb.KeySchema(["a", "b"])
+    /// >>> rb.RedDict.from_values(s, [1.0, 2.0]).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// ```
+    #[staticmethod]
+    #[pyo3(name = "from_values")]
+    fn py_from_values(schema: &Bound<KeySchema>, values: Vec<Option<f64>>) -> PyResult<Self> {
+        let schema = schema.get();
+        if values.len() != schema.keys.len() {
+            return Err(PyValueError::new_err(format!(
+                "expected {} values for schema, got {}",
+                schema.keys.len(),
+                values.len()
+            )));
+        }
+        let validity = Bitmap::from_fn(values.len(), |i| values[i].is_some());
+        let values = values.iter().map(|v| v.unwrap_or(f64::NAN)).collect();
         Ok(Self {
-            index: Arc::new(index),
+            keys: Arc::clone(&schema.keys),
+            index: Arc::clone(&schema.index),
             values: Arc::new(values),
-        })
+            validity: None,
+            frozen: false,
+        }
+        .with_validity(validity))
+    }
+
+    #[getter]
+    /// Returns the interned key layout of this dictionary.
+    ///
+    /// Dicts built from it with `RedDict(data, schema=...)` or
+    /// `RedDict.from_values` line up with this one without any hashing.
+    /// If an equal layout was interned first, this dict switches over to it.
+    fn schema(&mut self) -> KeySchema {
+        let schema = KeySchema::interned(Arc::clone(&self.keys), Arc::clone(&self.index));
+        self.keys = Arc::clone(&schema.keys);
+        self.index = Arc::clone(&schema.index);
+        schema
     }
 
     /// Adds a scalar value (single value) to every value in the dictionary.
@@ -73,10 +274,17 @@ impl RedDict {
         new
     }
 
-    /// Adds values (d1 + d2), aligned on d1s keys. Only keys from d1 are
+    /// Adds values (d1 + d2), aligned on d1s keys by default. Only keys from d1 are
     /// considered, if key from d1 is absent from d2, a fill value can optionally
     /// be used as the argument for +.
     ///
+    /// `how` selects the keys of the result: `"left"` (d1s keys, the default),
+    /// `"right"` (d2s keys), `"inner"` (keys in both) or `"outer"` (keys in
+    /// either). `fill_left` and `fill_right` override `fill` for keys missing
+    /// from d1 and d2 respectively. `fill=None` makes missing keys null. With
+    /// `strict=True` (or the module default set by `set_options`), differing
+    /// key sets raise `KeyMismatchError`.
+    ///
     /// # Examples
     ///
     /// ```python
@@ -86,11 +294,25 @@ impl RedDict {
     /// {'a': 1.0, 'b': 12.0}
     /// >>> d1.add(d2, fill=5.0).to_dict
     /// {'a': 6.0, 'b': 12.0}
+    /// >>> d3 = rb.RedDict({"b": 10.0, "c": 20.0})
+    /// >>> d1.add(d3, how="outer").to_dict
+    /// {'a': 1.0, 'b': 12.0, 'c': 20.0}
     /// ```
-    #[pyo3(signature = (other, fill=0.0))]
-    fn add(&self, other: &Bound<Self>, fill: f64) -> PyResult<Self> {
-        let other_ref = other.borrow();
-        Ok(merge(self, &other_ref, fill, |a, b| a + b))
+    #[pyo3(
+        name = "add",
+        signature = (other, fill=Some(0.0), how="left", fill_left=None, fill_right=None, strict=None)
+    )]
+    fn py_add(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a + b)
     }
 
     /// Subtracts a scalar value (single value) to every value in the dictionary.
@@ -111,10 +333,17 @@ impl RedDict {
         new
     }
 
-    /// Subtracts values (d1 - d2), aligned on d1s keys. Only keys from d1 are
+    /// Subtracts values (d1 - d2), aligned on d1s keys by default. Only keys from d1 are
     /// considered, if key from d1 is absent from d2, a fill value can optionally
     /// be used as the argument for -.
     ///
+    /// `how` selects the keys of the result: `"left"` (d1s keys, the default),
+    /// `"right"` (d2s keys), `"inner"` (keys in both) or `"outer"` (keys in
+    /// either). `fill_left` and `fill_right` override `fill` for keys missing
+    /// from d1 and d2 respectively. `fill=None` makes missing keys null. With
+    /// `strict=True` (or the module default set by `set_options`), differing
+    /// key sets raise `KeyMismatchError`.
+    ///
     /// # Examples
     ///
     /// ```python
@@ -123,10 +352,21 @@ impl RedDict {
     /// >>> d1.subtract(d2).to_dict
     /// {'a': 10.0, 'b': 3.0}
     /// ```
-    #[pyo3(signature = (other, fill=0.0))]
-    fn subtract(&self, other: &Bound<Self>, fill: f64) -> PyResult<Self> {
-        let other_ref = other.borrow();
-        Ok(merge(self, &other_ref, fill, |a, b| a - b))
+    #[pyo3(
+        name = "subtract",
+        signature = (other, fill=Some(0.0), how="left", fill_left=None, fill_right=None, strict=None)
+    )]
+    fn py_subtract(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a - b)
     }
 
     /// Multiplies a scalar value (single value) to every value in the dictionary.
@@ -147,10 +387,17 @@ impl RedDict {
         new
     }
 
-    /// Multiplies values (d1 * d2), aligned on d1s keys. Only keys from d1 are
+    /// Multiplies values (d1 * d2), aligned on d1s keys by default. Only keys from d1 are
     /// considered, if key from d1 is absent from d2, a fill value can optionally
     /// be used as the argument for *.
     ///
+    /// `how` selects the keys of the result: `"left"` (d1s keys, the default),
+    /// `"right"` (d2s keys), `"inner"` (keys in both) or `"outer"` (keys in
+    /// either). `fill_left` and `fill_right` override `fill` for keys missing
+    /// from d1 and d2 respectively. `fill=None` makes missing keys null. With
+    /// `strict=True` (or the module default set by `set_options`), differing
+    /// key sets raise `KeyMismatchError`.
+    ///
     /// # Examples
     ///
     /// ```python
@@ -159,10 +406,21 @@ impl RedDict {
     /// >>> d1.multiply(d2).to_dict
     /// {'a': 2.0, 'b': 30.0}
     /// ```
-    #[pyo3(signature = (other, fill=1.0))]
-    fn multiply(&self, other: &Bound<Self>, fill: f64) -> PyResult<Self> {
-        let other_ref = other.borrow();
-        Ok(merge(self, &other_ref, fill, |a, b| a * b))
+    #[pyo3(
+        name = "multiply",
+        signature = (other, fill=Some(1.0), how="left", fill_left=None, fill_right=None, strict=None)
+    )]
+    fn py_multiply(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a * b)
     }
 
     /// Divides a scalar value (single value) to every value in the dictionary.
@@ -183,10 +441,17 @@ impl RedDict {
         new
     }
 
-    /// Divides values (d1 / d2), aligned on d1s keys. Only keys from d1 are
+    /// Divides values (d1 / d2), aligned on d1s keys by default. Only keys from d1 are
     /// considered, if key from d1 is absent from d2, a fill value can optionally
     /// be used as the argument for /.
     ///
+    /// `how` selects the keys of the result: `"left"` (d1s keys, the default),
+    /// `"right"` (d2s keys), `"inner"` (keys in both) or `"outer"` (keys in
+    /// either). `fill_left` and `fill_right` override `fill` for keys missing
+    /// from d1 and d2 respectively. `fill=None` makes missing keys null. With
+    /// `strict=True` (or the module default set by `set_options`), differing
+    /// key sets raise `KeyMismatchError`.
+    ///
     /// # Examples
     ///
     /// ```python
@@ -195,507 +460,5163 @@ impl RedDict {
     /// >>> d1.divide(d2).to_dict
     /// {'a': 10.0, 'b': 3.0}
     /// ```
-    #[pyo3(signature = (other, fill=1.0))]
-    fn divide(&self, other: &Bound<Self>, fill: f64) -> PyResult<Self> {
-        let other_ref = other.borrow();
-        Ok(merge(self, &other_ref, fill, |a, b| a / b))
+    #[pyo3(
+        name = "divide",
+        signature = (other, fill=Some(1.0), how="left", fill_left=None, fill_right=None, strict=None)
+    )]
+    fn py_divide(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a / b)
     }
 
-    /// Sum of values.
+    /// Element-wise maximum of d1 and d2.
+    ///
+    /// Takes the same alignment options as `add`; `fill` defaults to
+    /// -inf, so keys missing from d2 keep d1s value. A NaN on either side
+    /// gives NaN, as in `numpy.maximum`.
     ///
     /// # Examples
     ///
     /// ```python
-    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0, "c": 3.0})
-    /// >>> d.sum()
-    /// 6.0
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 5.0})
+    /// >>> d2 = rb.RedDict({"a": 3.0})
+    /// >>> d1.maximum(d2).to_dict
+    /// {'a': 3.0, 'b': 5.0}
     /// ```
-    fn sum(&self) -> f64 {
-        self.values.iter().sum()
+    #[pyo3(signature = (other, fill=Some(f64::NEG_INFINITY), how="left", fill_left=None, fill_right=None, strict=None))]
+    fn maximum(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| ops::maximum(*a, *b))
     }
 
-    /// Product of values.
+    /// `maximum` with a scalar (single value) as the right-hand side.
     ///
     /// # Examples
     ///
     /// ```python
-    /// >>> d = rb.RedDict({"a": 2.0, "b": 3.0, "c": 4.0})
-    /// >>> d.product()
-    /// 24.0
+    /// >>> rb.RedDict({"a": 1.0, "b": 5.0}).maximum_scalar(2.0).to_dict
+    /// {'a': 2.0, 'b': 5.0}
     /// ```
-    fn product(&self) -> f64 {
-        self.values.iter().product()
+    #[must_use]
+    fn maximum_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| ops::maximum(a, value))
     }
 
-    /// Sets all values to passed in value
+    /// Element-wise minimum of d1 and d2.
+    ///
+    /// Takes the same alignment options as `add`; `fill` defaults to
+    /// inf, so keys missing from d2 keep d1s value. A NaN on either side
+    /// gives NaN, as in `numpy.minimum`.
     ///
     /// # Examples
     ///
     /// ```python
-    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
-    /// >>> d.reset(99.0).to_dict
-    /// {'a': 99.0, 'b': 99.0}
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 5.0})
+    /// >>> d2 = rb.RedDict({"a": 3.0})
+    /// >>> d1.minimum(d2).to_dict
+    /// {'a': 1.0, 'b': 5.0}
+    /// ```
+    #[pyo3(signature = (other, fill=Some(f64::INFINITY), how="left", fill_left=None, fill_right=None, strict=None))]
+    fn minimum(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| ops::minimum(*a, *b))
+    }
+
+    /// `minimum` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 1.0, "b": 5.0}).minimum_scalar(2.0).to_dict
+    /// {'a': 1.0, 'b': 2.0}
     /// ```
     #[must_use]
-    fn reset(&self, value: f64) -> Self {
-        let mut new = self.clone();
-        Arc::make_mut(&mut new.values)
-            .iter_mut()
-            .for_each(|val| *val = value);
-        new
+    fn minimum_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| ops::minimum(a, value))
     }
 
-    #[getter]
-    /// Returns the underlying dictionary.
+    /// Raises d1 to the power of d2, element-wise (d1 ** d2).
+    ///
+    /// Takes the same alignment options as `add`; `fill` defaults to
+    /// 1.0, so keys missing from d2 keep d1s value.
     ///
     /// # Examples
     ///
     /// ```python
-    /// >>> d = rb.RedDict({"x": 42.0})
-    /// >>> d.to_dict
-    /// {'x': 42.0}
+    /// >>> d1 = rb.RedDict({"a": 2.0, "b": 3.0})
+    /// >>> d2 = rb.RedDict({"a": 3.0})
+    /// >>> d1.power(d2).to_dict
+    /// {'a': 8.0, 'b': 3.0}
     /// ```
-    fn to_dict(&self) -> HashMap<String, f64> {
-        let mut map = HashMap::with_capacity(self.values.len());
-        for (k, v) in self.index.iter() {
-            map.insert(k.clone(), self.values[*v]);
-        }
-        map
+    #[pyo3(signature = (other, fill=Some(1.0), how="left", fill_left=None, fill_right=None, strict=None))]
+    fn power(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a.powf(*b))
     }
-}
 
-/// Shared implementation for binary element-wise operations.
-///
-/// `fill` is the value used when `other` is missing a key present in `self`.
-fn merge<F>(this: &RedDict, other: &RedDict, fill: f64, f: F) -> RedDict
-where
-    F: Fn(&f64, &f64) -> f64,
-{
-    let mut new = this.clone();
-    let new_vals = Arc::make_mut(&mut new.values);
+    /// `power` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 2.0, "b": 3.0}).power_scalar(2.0).to_dict
+    /// {'a': 4.0, 'b': 9.0}
+    /// ```
+    #[must_use]
+    fn power_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| a.powf(value))
+    }
 
-    if Arc::ptr_eq(&this.index, &other.index)
-        || (this.index.len() == other.index.len() && this.index == other.index)
-    {
-        for (nv, ov) in new_vals.iter_mut().zip(other.values.iter()) {
-            *nv = f(nv, ov);
-        }
-    } else {
-        for (key, &i) in this.index.iter() {
-            let rhs = other
-                .index
-                .get(key)
-                .map(|&j| other.values[j])
-                .unwrap_or(fill);
-            new_vals[i] = f(&new_vals[i], &rhs);
-        }
+    /// Remainder of d1 / d2 with Python's sign rules (d1 % d2).
+    ///
+    /// Takes the same alignment options as `add`, but unlike `add` and
+    /// `divide` its `fill` defaults to `None`: no right-hand value leaves d1
+    /// unchanged, so keys missing from d2 give nulls unless `fill` is passed.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 7.0, "b": -7.0})
+    /// >>> d2 = rb.RedDict({"a": 3.0, "b": 3.0})
+    /// >>> d1.modulo(d2).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// >>> d1.modulo(rb.RedDict({"a": 3.0})).to_dict
+    /// {'a': 1.0, 'b': None}
+    /// ```
+    #[pyo3(signature = (other, fill=None, how="left", fill_left=None, fill_right=None, strict=None))]
+    fn modulo(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| ops::py_mod(*a, *b))
     }
 
-    new
-}
+    /// `modulo` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 7.0, "b": -7.0}).modulo_scalar(3.0).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// ```
+    #[must_use]
+    fn modulo_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| ops::py_mod(a, value))
+    }
 
-/// A Python module implemented in Rust.
-#[pymodule]
-fn redbear(m: &Bound<PyModule>) -> PyResult<()> {
-    m.add_class::<RedDict>()?;
-    Ok(())
-}
+    /// Floor division of d1 by d2 with Python's rounding (d1 // d2).
+    ///
+    /// Takes the same alignment options as `add`, but `fill` defaults to
+    /// `None` rather than an identity like `divide`'s 1.0, since `x // 1.0`
+    /// drops the fraction. Keys missing from d2 give nulls unless `fill` is
+    /// passed.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 7.0, "b": -7.0})
+    /// >>> d2 = rb.RedDict({"a": 2.0, "b": 2.0})
+    /// >>> d1.floor_divide(d2).to_dict
+    /// {'a': 3.0, 'b': -4.0}
+    /// ```
+    #[pyo3(signature = (other, fill=None, how="left", fill_left=None, fill_right=None, strict=None))]
+    fn floor_divide(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| ops::py_floor_div(*a, *b))
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use pyo3::{types::PyDict, Py, Python};
+    /// `floor_divide` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 7.0, "b": -7.0}).floor_divide_scalar(2.0).to_dict
+    /// {'a': 3.0, 'b': -4.0}
+    /// ```
+    #[must_use]
+    fn floor_divide_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| ops::py_floor_div(a, value))
+    }
 
-    fn make_dict(py: Python<'_>, entries: &[(&str, f64)]) -> RedDict {
-        let dict = PyDict::new(py);
-        for (k, v) in entries {
-            dict.set_item(*k, *v).unwrap();
-        }
-        RedDict::new(&dict).unwrap()
+    /// Element-wise `atan2(d1, d2)`, the angle of the point (d2, d1) in radians.
+    ///
+    /// Takes the same alignment options as `add`, but `fill` defaults to
+    /// `None` rather than an identity as in `add`: no d2 value gives back
+    /// d1, so keys missing from d2 give nulls unless `fill` is passed.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0})
+    /// >>> d2 = rb.RedDict({"a": 1.0})
+    /// >>> d1.atan2(d2).to_dict
+    /// {'a': 0.7853981633974483}
+    /// ```
+    #[pyo3(signature = (other, fill=None, how="left", fill_left=None, fill_right=None, strict=None))]
+    fn atan2(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a.atan2(*b))
     }
 
-    #[test]
-    fn test_new_from_empty_dict() {
-        Python::initialize();
-        Python::attach(|py| {
-            let dict = PyDict::new(py);
-            let rd = RedDict::new(&dict).unwrap();
-            assert_eq!(rd.to_dict().len(), 0);
-        });
+    /// `atan2` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 1.0}).atan2_scalar(-1.0).to_dict
+    /// {'a': 2.356194490192345}
+    /// ```
+    #[must_use]
+    fn atan2_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| a.atan2(value))
     }
 
-    #[test]
-    fn test_new_from_single_entry() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 42.0)]);
-            let map = rd.to_dict();
-            assert_eq!(map.get("x"), Some(&42.0));
-            assert_eq!(map.len(), 1);
-        });
+    /// Element-wise `hypot(d1, d2)`, i.e. `sqrt(d1**2 + d2**2)` without overflow.
+    ///
+    /// Takes the same alignment options as `add`; `fill` defaults to
+    /// 0.0, so keys missing from d2 give `abs(d1)`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 3.0, "b": -2.0})
+    /// >>> d2 = rb.RedDict({"a": 4.0})
+    /// >>> d1.hypot(d2).to_dict
+    /// {'a': 5.0, 'b': 2.0}
+    /// ```
+    #[pyo3(signature = (other, fill=Some(0.0), how="left", fill_left=None, fill_right=None, strict=None))]
+    fn hypot(
+        &self,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        let args = JoinArgs::new(fill, how, fill_left, fill_right, strict)?;
+        self.binary(other, args, |a, b| a.hypot(*b))
     }
 
-    #[test]
-    fn test_new_from_multiple_entries() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
-            let map = rd.to_dict();
-            assert_eq!(map.get("a"), Some(&1.0));
-            assert_eq!(map.get("b"), Some(&2.0));
-            assert_eq!(map.get("c"), Some(&3.0));
-        });
+    /// `hypot` with a scalar (single value) as the right-hand side.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 3.0}).hypot_scalar(4.0).to_dict
+    /// {'a': 5.0}
+    /// ```
+    #[must_use]
+    fn hypot_scalar(&self, value: f64) -> Self {
+        self.map_values(|a| a.hypot(value))
     }
 
-    #[test]
-    fn test_add_scalar() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 1.0), ("b", -2.0)]);
-            let result = rd.add_scalar(3.0);
-            assert_eq!(result.to_dict().get("a"), Some(&4.0));
-            assert_eq!(result.to_dict().get("b"), Some(&1.0));
-        });
+    /// In-place `add`: adds `other` into this RedDict's own values instead
+    /// of returning a new RedDict, and returns `None`.
+    ///
+    /// The keys never change, so only the `how="left"` behaviour is
+    /// available: keys `other` lacks use `fill` (or become null when `fill`
+    /// is `None`), and `strict` works as for `add`.
+    ///
+    /// The buffer is reused when this RedDict is its only owner. When the
+    /// values are shared, e.g. with a RedDict returned by `fillna` on a dict
+    /// without nulls, they are copied first, so RedDicts sharing storage
+    /// never see each other's in-place updates.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d2 = rb.RedDict({"a": 10.0})
+    /// >>> d1.iadd(d2)
+    /// >>> d1.to_dict
+    /// {'a': 11.0, 'b': 2.0}
+    /// ```
+    #[pyo3(signature = (other, fill=Some(0.0), strict=None))]
+    fn iadd(
+        slf: &Bound<Self>,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<()> {
+        Self::update_in_place(slf, other, fill, options::resolve_strict(strict), |a, b| {
+            a + b
+        })
     }
 
-    #[test]
-    fn test_subtract_scalar() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 5.0), ("b", 3.0)]);
-            let result = rd.subtract_scalar(2.0);
-            assert_eq!(result.to_dict().get("a"), Some(&3.0));
-            assert_eq!(result.to_dict().get("b"), Some(&1.0));
-        });
+    /// In-place `subtract`, with the same semantics as `iadd`.
+    #[pyo3(signature = (other, fill=Some(0.0), strict=None))]
+    fn isubtract(
+        slf: &Bound<Self>,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<()> {
+        Self::update_in_place(slf, other, fill, options::resolve_strict(strict), |a, b| {
+            a - b
+        })
     }
 
-    #[test]
-    fn test_add_scalar_negative() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 10.0)]);
-            let result = rd.add_scalar(-5.0);
-            assert_eq!(result.to_dict().get("a"), Some(&5.0));
-        });
+    /// In-place `multiply`, with the same semantics as `iadd`.
+    #[pyo3(signature = (other, fill=Some(1.0), strict=None))]
+    fn imultiply(
+        slf: &Bound<Self>,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<()> {
+        Self::update_in_place(slf, other, fill, options::resolve_strict(strict), |a, b| {
+            a * b
+        })
     }
 
-    #[test]
-    fn test_operations_return_new_instance() {
-        Python::initialize();
-        Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 1.0)]);
-            let added = rd.add_scalar(1.0);
-            let subtracted = rd.subtract_scalar(1.0);
-            assert_eq!(rd.to_dict().get("a"), Some(&1.0));
-            assert_eq!(added.to_dict().get("a"), Some(&2.0));
-            assert_eq!(subtracted.to_dict().get("a"), Some(&0.0));
-        });
+    /// In-place `divide`, with the same semantics as `iadd`.
+    #[pyo3(signature = (other, fill=Some(1.0), strict=None))]
+    fn idivide(
+        slf: &Bound<Self>,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<()> {
+        Self::update_in_place(slf, other, fill, options::resolve_strict(strict), |a, b| {
+            a / b
+        })
     }
 
-    #[test]
-    fn test_add_uses_fill_for_missing_keys() {
-        Python::initialize();
-        Python::attach(|py| {
-            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
-            let right = make_dict(py, &[("b", 10.0), ("c", 100.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.add(py_right.bind(py), 5.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&6.0)); // fill used
-            assert_eq!(result.to_dict().get("b"), Some(&12.0)); // right value used
-            assert!(!result.to_dict().contains_key("c"));
-        });
+    /// In-place `add_scalar`. Reuses the buffer like `iadd`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d.iadd_scalar(5.0)
+    /// >>> d.to_dict
+    /// {'a': 6.0, 'b': 7.0}
+    /// ```
+    fn iadd_scalar(&mut self, value: f64) -> PyResult<()> {
+        self.check_mutable()?;
+        self.map_values_in_place(|v| v + value);
+        Ok(())
     }
 
-    #[test]
-    fn test_subtract_uses_fill_for_missing_keys() {
-        Python::initialize();
-        Python::attach(|py| {
-            let left = make_dict(py, &[("a", 10.0), ("b", 5.0)]);
-            let right = make_dict(py, &[("b", 2.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.subtract(py_right.bind(py), 3.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&7.0)); // fill used
-            assert_eq!(result.to_dict().get("b"), Some(&3.0)); // right value used
-        });
+    /// In-place `subtract_scalar`.
+    fn isubtract_scalar(&mut self, value: f64) -> PyResult<()> {
+        self.check_mutable()?;
+        self.map_values_in_place(|v| v - value);
+        Ok(())
     }
 
-    #[test]
-    fn test_multiply_uses_fill_for_missing_keys() {
-        Python::initialize();
-        Python::attach(|py| {
+    /// In-place `multiply_scalar`.
+    fn imultiply_scalar(&mut self, value: f64) -> PyResult<()> {
+        self.check_mutable()?;
+        self.map_values_in_place(|v| v * value);
+        Ok(())
+    }
+
+    /// In-place `divide_scalar`.
+    fn idivide_scalar(&mut self, value: f64) -> PyResult<()> {
+        self.check_mutable()?;
+        self.map_values_in_place(|v| v / value);
+        Ok(())
+    }
+
+    /// Sum of values. Two options, shared by every reduction, pick what is
+    /// left out:
+    ///
+    /// - `skipna` (default `True`) skips nulls, and only nulls. With
+    ///   `skipna=False` any null makes the result `None`.
+    /// - `skipnan` (default `False`) skips NaN values. Without it a NaN makes
+    ///   the result NaN.
+    ///
+    /// This differs from pandas, whose `skipna` skips NaN because pandas
+    /// stores missing values as NaN. Here a null and a NaN are distinct, so
+    /// `skipna=True` alone still lets NaN through.
+    ///
+    /// `method` picks the summation algorithm: `"naive"` (left to right, the
+    /// fastest), `"pairwise"` (numpy's approach) or `"kahan"` (compensated,
+    /// accurate even with heavy cancellation between large and small values).
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0, "c": 3.0})
+    /// >>> d.sum()
+    /// 6.0
+    /// >>> rb.RedDict({"a": 1.0, "b": 1e100, "c": 1.0, "d": -1e100}).sum(method="kahan")
+    /// 2.0
+    /// ```
+    #[pyo3(name = "sum", signature = (skipna=true, skipnan=false, method="naive"))]
+    fn py_sum(&self, skipna: bool, skipnan: bool, method: &str) -> PyResult<Option<f64>> {
+        let method = Summation::parse(method)?;
+        Ok(self.aggregate(skipna, skipnan, |values| method.sum(values)))
+    }
+
+    /// Product of values. Nulls are skipped unless `skipna=False`, in which
+    /// case any null makes the result `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 2.0, "b": 3.0, "c": 4.0})
+    /// >>> d.product()
+    /// 24.0
+    /// ```
+    #[pyo3(name = "product", signature = (skipna=true, skipnan=false))]
+    fn py_product(&self, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.aggregate(skipna, skipnan, |values| values.product())
+    }
+
+    /// Arithmetic mean of values; NaN when there are none. As in `sum`,
+    /// `skipna` skips only nulls and `skipnan` only NaN, unlike pandas'
+    /// `skipna`. `method` is the summation algorithm, as for `sum`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0, "c": None})
+    /// >>> d.mean()
+    /// 1.5
+    /// >>> d.mean(skipna=False) is None
+    /// True
+    /// ```
+    #[pyo3(signature = (skipna=true, skipnan=false, method="naive"))]
+    fn mean(&self, skipna: bool, skipnan: bool, method: &str) -> PyResult<Option<f64>> {
+        let method = Summation::parse(method)?;
+        Ok(self.aggregate(skipna, skipnan, |values| stats::mean(values, method)))
+    }
+
+    /// Smallest value; NaN when there are none.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 3.0, "b": float("nan"), "c": 1.0})
+    /// >>> d.min()
+    /// nan
+    /// >>> d.min(skipnan=True)
+    /// 1.0
+    /// ```
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn min(&self, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.extreme(skipna, skipnan, |a, b| a < b)
+            .map(|found| found.map_or(f64::NAN, |(_, v)| v))
+    }
+
+    /// Largest value; NaN when there are none.
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn max(&self, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.extreme(skipna, skipnan, |a, b| a > b)
+            .map(|found| found.map_or(f64::NAN, |(_, v)| v))
+    }
+
+    /// Key of the smallest value. Ties go to the first key; a NaN counts as
+    /// the minimum unless `skipnan=True`. Raises `ValueError` when there are
+    /// no values.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 3.0, "b": 1.0, "c": 1.0}).argmin()
+    /// 'b'
+    /// ```
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn argmin(&self, skipna: bool, skipnan: bool) -> PyResult<Option<&str>> {
+        self.arg_extreme("argmin", skipna, skipnan, |a, b| a < b)
+    }
+
+    /// Key of the largest value, with the same rules as `argmin`.
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn argmax(&self, skipna: bool, skipnan: bool) -> PyResult<Option<&str>> {
+        self.arg_extreme("argmax", skipna, skipnan, |a, b| a > b)
+    }
+
+    /// Variance with `ddof` delta degrees of freedom (the population
+    /// variance by default, like numpy). NaN when there are no more than
+    /// `ddof` values.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0, "c": 3.0, "d": 4.0})
+    /// >>> d.var()
+    /// 1.25
+    /// >>> d.var(ddof=1)
+    /// 1.6666666666666667
+    /// ```
+    #[pyo3(signature = (ddof=0, skipna=true, skipnan=false))]
+    fn var(&self, ddof: usize, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.aggregate(skipna, skipnan, |values| stats::variance(values, ddof))
+    }
+
+    /// Standard deviation, the square root of `var` with the same options.
+    #[pyo3(signature = (ddof=0, skipna=true, skipnan=false))]
+    fn std(&self, ddof: usize, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.var(ddof, skipna, skipnan).map(f64::sqrt)
+    }
+
+    /// Median of values; NaN when there are none.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 4.0, "b": 1.0, "c": 3.0, "d": 2.0}).median()
+    /// 2.5
+    /// ```
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn median(&self, skipna: bool, skipnan: bool) -> Option<f64> {
+        self.aggregate(skipna, skipnan, |values| {
+            stats::quantile(&mut values.collect::<Vec<_>>(), 0.5)
+        })
+    }
+
+    /// The `q`-th quantile, for `q` between 0 and 1, interpolating linearly
+    /// between the closest values like numpy's default.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 4.0, "b": 1.0, "c": 3.0, "d": 2.0}).quantile(0.25)
+    /// 1.75
+    /// ```
+    #[pyo3(signature = (q, skipna=true, skipnan=false))]
+    fn quantile(&self, q: f64, skipna: bool, skipnan: bool) -> PyResult<Option<f64>> {
+        if !(0.0..=1.0).contains(&q) {
+            return Err(PyValueError::new_err(format!(
+                "quantile must be between 0 and 1, got {q}"
+            )));
+        }
+        Ok(self.aggregate(skipna, skipnan, |values| {
+            stats::quantile(&mut values.collect::<Vec<_>>(), q)
+        }))
+    }
+
+    /// Number of non-null values, also leaving out NaN when `skipnan=True`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": None, "c": float("nan")})
+    /// >>> d.count()
+    /// 2
+    /// >>> d.count(skipnan=True)
+    /// 1
+    /// ```
+    #[pyo3(signature = (skipnan=false))]
+    fn count(&self, skipnan: bool) -> usize {
+        self.aggregate(true, skipnan, |values| values.count())
+            .unwrap_or_default()
+    }
+
+    /// Whether any value is non-zero. NaN counts as true, as in Python.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 0.0, "b": 2.0}).any()
+    /// True
+    /// ```
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn any(&self, skipna: bool, skipnan: bool) -> Option<bool> {
+        self.aggregate(skipna, skipnan, |mut values| {
+            Iterator::any(&mut values, |v| v != 0.0)
+        })
+    }
+
+    /// Whether every value is non-zero; true when there are none.
+    #[pyo3(signature = (skipna=true, skipnan=false))]
+    fn all(&self, skipna: bool, skipnan: bool) -> Option<bool> {
+        self.aggregate(skipna, skipnan, |mut values| {
+            Iterator::all(&mut values, |v| v != 0.0)
+        })
+    }
+
+    /// The `p`-norm of the values. `p=inf` gives the largest magnitude and
+    /// `p=0` the number of non-zero values.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 3.0, "b": -4.0})
+    /// >>> d.norm()
+    /// 5.0
+    /// >>> d.norm(1)
+    /// 7.0
+    /// >>> d.norm(float("inf"))
+    /// 4.0
+    /// ```
+    #[pyo3(signature = (p=2.0, skipna=true, skipnan=false))]
+    fn norm(&self, p: f64, skipna: bool, skipnan: bool) -> PyResult<Option<f64>> {
+        if p.is_nan() || p < 0.0 {
+            return Err(PyValueError::new_err(format!(
+                "norm order must be non-negative, got {p}"
+            )));
+        }
+        Ok(self.aggregate(skipna, skipnan, |values| stats::norm(values, p)))
+    }
+
+    /// Dot product, treating both RedDicts as sparse vectors: keys missing
+    /// from one side (or null there) count as `0.0`. `method` is the
+    /// summation algorithm, as for `sum`.
+    ///
+    /// Like the other similarity and distance methods, this walks the key
+    /// alignment directly and reduces to a number without building an
+    /// intermediate RedDict.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 2.0, "c": 3.0})
+    /// >>> d2 = rb.RedDict({"c": 1.0, "a": 2.0, "z": 5.0})
+    /// >>> d1.dot(d2)
+    /// 5.0
+    /// ```
+    #[pyo3(signature = (other, method="naive"))]
+    fn dot(&self, other: &Bound<Self>, method: &str) -> PyResult<f64> {
+        let method = Summation::parse(method)?;
+        let other = other.borrow();
+        Ok(method.sum(sparse_pairs(self, &other, false).map(|(a, b)| a * b)))
+    }
+
+    /// Cosine similarity, `dot / (norm(self) * norm(other))`, with missing
+    /// keys as `0.0`. NaN when either side has no non-zero values.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 1.0})
+    /// >>> d2 = rb.RedDict({"a": 2.0})
+    /// >>> round(d1.cosine(d2), 6)
+    /// 0.707107
+    /// ```
+    fn cosine(&self, other: &Bound<Self>) -> f64 {
+        let other = other.borrow();
+        let (ab, aa, bb) = sparse_pairs(self, &other, true)
+            .fold((0.0, 0.0, 0.0), |(ab, aa, bb), (a, b)| {
+                (ab + a * b, aa + a * a, bb + b * b)
+            });
+        ab / (aa.sqrt() * bb.sqrt())
+    }
+
+    /// Euclidean distance over the union of keys, with missing keys as `0.0`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 4.0})
+    /// >>> d2 = rb.RedDict({"a": 4.0, "c": 4.0})
+    /// >>> d1.euclidean(d2)
+    /// 6.4031242374328485
+    /// ```
+    fn euclidean(&self, other: &Bound<Self>) -> f64 {
+        let other = other.borrow();
+        sparse_pairs(self, &other, true)
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    /// Manhattan (L1) distance over the union of keys, with missing keys as
+    /// `0.0`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 4.0})
+    /// >>> d2 = rb.RedDict({"a": 4.0, "c": 4.0})
+    /// >>> d1.manhattan(d2)
+    /// 11.0
+    /// ```
+    fn manhattan(&self, other: &Bound<Self>) -> f64 {
+        let other = other.borrow();
+        sparse_pairs(self, &other, true)
+            .map(|(a, b)| (a - b).abs())
+            .sum()
+    }
+
+    /// Weighted Jaccard similarity, `sum(min) / sum(max)` over the union of
+    /// keys with missing keys as `0.0`. For 0/1 weights this is the Jaccard
+    /// index of the two key sets. Meant for non-negative weights; NaN when
+    /// both sides are all zero.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 1.0, "c": 1.0})
+    /// >>> d2 = rb.RedDict({"b": 1.0, "c": 1.0, "d": 1.0})
+    /// >>> d1.jaccard(d2)
+    /// 0.5
+    /// ```
+    fn jaccard(&self, other: &Bound<Self>) -> f64 {
+        let other = other.borrow();
+        let (low, high) = sparse_pairs(self, &other, true)
+            .fold((0.0, 0.0), |(low, high), (a, b)| {
+                (low + a.min(b), high + a.max(b))
+            });
+        low / high
+    }
+
+    /// Number of null entries.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": None})
+    /// >>> d.null_count()
+    /// 1
+    /// ```
+    fn null_count(&self) -> usize {
+        self.validity.as_ref().map_or(0, |v| v.count_unset())
+    }
+
+    /// Replaces nulls with `value`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": None})
+    /// >>> d.fillna(0.0).to_dict
+    /// {'a': 1.0, 'b': 0.0}
+    /// ```
+    #[must_use]
+    fn fillna(&self, value: f64) -> Self {
+        let Some(validity) = &self.validity else {
+            return self.clone();
+        };
+        let mut new = self.clone();
+        for (i, val) in Arc::make_mut(&mut new.values).iter_mut().enumerate() {
+            if !validity.get(i) {
+                *val = value;
+            }
+        }
+        new.validity = None;
+        new
+    }
+
+    /// Sets all values to passed in value, including nulls.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d.reset(99.0).to_dict
+    /// {'a': 99.0, 'b': 99.0}
+    /// ```
+    #[must_use]
+    fn reset(&self, value: f64) -> Self {
+        let mut new = self.clone();
+        Arc::make_mut(&mut new.values)
+            .iter_mut()
+            .for_each(|val| *val = value);
+        new.validity = None;
+        new
+    }
+
+    /// Absolute value of every entry.
+    ///
+    /// Like every unary method, the result shares its key layout with `self`,
+    /// so it stays on the fast path when combined with it again. Nulls stay
+    /// null.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": -1.5, "b": 2.0})
+    /// >>> d.abs().to_dict
+    /// {'a': 1.5, 'b': 2.0}
+    /// >>> d.add(d.abs()).to_dict
+    /// {'a': 0.0, 'b': 4.0}
+    /// ```
+    #[must_use]
+    fn abs(&self) -> Self {
+        self.map_values(f64::abs)
+    }
+
+    /// Negates every entry (`-d`).
+    #[must_use]
+    fn neg(&self) -> Self {
+        self.map_values(|v| -v)
+    }
+
+    /// Square root of every entry; negative entries give NaN.
+    #[must_use]
+    fn sqrt(&self) -> Self {
+        self.map_values(f64::sqrt)
+    }
+
+    /// `e` raised to every entry.
+    #[must_use]
+    fn exp(&self) -> Self {
+        self.map_values(f64::exp)
+    }
+
+    /// Natural logarithm of every entry.
+    #[must_use]
+    fn log(&self) -> Self {
+        self.map_values(f64::ln)
+    }
+
+    /// `log(1 + x)`, accurate for entries near zero.
+    #[must_use]
+    fn log1p(&self) -> Self {
+        self.map_values(f64::ln_1p)
+    }
+
+    /// Base-10 logarithm of every entry.
+    #[must_use]
+    fn log10(&self) -> Self {
+        self.map_values(f64::log10)
+    }
+
+    /// Largest integer less than or equal to every entry.
+    #[must_use]
+    fn floor(&self) -> Self {
+        self.map_values(f64::floor)
+    }
+
+    /// Smallest integer greater than or equal to every entry.
+    #[must_use]
+    fn ceil(&self) -> Self {
+        self.map_values(f64::ceil)
+    }
+
+    /// Rounds every entry to `ndigits` decimal places, with ties going to the
+    /// even neighbour like Python's `round`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 2.5, "b": 1.234, "c": 1250.0})
+    /// >>> d.round().to_dict
+    /// {'a': 2.0, 'b': 1.0, 'c': 1250.0}
+    /// >>> d.round(2).to_dict
+    /// {'a': 2.5, 'b': 1.23, 'c': 1250.0}
+    /// >>> d.round(-2).to_dict
+    /// {'a': 0.0, 'b': 0.0, 'c': 1200.0}
+    /// ```
+    #[pyo3(signature = (ndigits=0))]
+    #[must_use]
+    fn round(&self, ndigits: i32) -> Self {
+        self.map_values(|v| ops::round(v, ndigits))
+    }
+
+    /// -1.0, 0.0 or 1.0 depending on the sign of every entry. NaN stays NaN.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": -3.0, "b": 0.0, "c": 7.0}).sign().to_dict
+    /// {'a': -1.0, 'b': 0.0, 'c': 1.0}
+    /// ```
+    #[must_use]
+    fn sign(&self) -> Self {
+        self.map_values(ops::sign)
+    }
+
+    /// Limits every entry to the range `[lo, hi]`. Either bound may be
+    /// omitted, but not both. Raises `ValueError` if `lo > hi`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": -3.0, "b": 0.5, "c": 7.0})
+    /// >>> d.clip(0.0, 1.0).to_dict
+    /// {'a': 0.0, 'b': 0.5, 'c': 1.0}
+    /// >>> d.clip(hi=0.0).to_dict
+    /// {'a': -3.0, 'b': 0.0, 'c': 0.0}
+    /// ```
+    #[pyo3(signature = (lo=None, hi=None))]
+    fn clip(&self, lo: Option<f64>, hi: Option<f64>) -> PyResult<Self> {
+        if lo.is_none() && hi.is_none() {
+            return Err(PyValueError::new_err(
+                "clip needs at least one of lo and hi",
+            ));
+        }
+        let lo = lo.unwrap_or(f64::NEG_INFINITY);
+        let hi = hi.unwrap_or(f64::INFINITY);
+        if lo > hi {
+            return Err(PyValueError::new_err(format!(
+                "clip needs lo <= hi, got lo={lo} and hi={hi}"
+            )));
+        }
+        Ok(self.map_values(|v| ops::clip(v, lo, hi)))
+    }
+
+    /// `1 / x` for every entry.
+    #[must_use]
+    fn reciprocal(&self) -> Self {
+        self.map_values(f64::recip)
+    }
+
+    /// Sine of every entry, in radians.
+    #[must_use]
+    fn sin(&self) -> Self {
+        self.map_values(f64::sin)
+    }
+
+    /// Cosine of every entry, in radians.
+    #[must_use]
+    fn cos(&self) -> Self {
+        self.map_values(f64::cos)
+    }
+
+    /// Tangent of every entry, in radians.
+    #[must_use]
+    fn tan(&self) -> Self {
+        self.map_values(f64::tan)
+    }
+
+    /// Arc sine of every entry, in radians.
+    #[must_use]
+    fn asin(&self) -> Self {
+        self.map_values(f64::asin)
+    }
+
+    /// Arc cosine of every entry, in radians.
+    #[must_use]
+    fn acos(&self) -> Self {
+        self.map_values(f64::acos)
+    }
+
+    /// Arc tangent of every entry, in radians.
+    #[must_use]
+    fn atan(&self) -> Self {
+        self.map_values(f64::atan)
+    }
+
+    /// Hyperbolic sine of every entry.
+    #[must_use]
+    fn sinh(&self) -> Self {
+        self.map_values(f64::sinh)
+    }
+
+    /// Hyperbolic cosine of every entry.
+    #[must_use]
+    fn cosh(&self) -> Self {
+        self.map_values(f64::cosh)
+    }
+
+    /// Hyperbolic tangent of every entry.
+    #[must_use]
+    fn tanh(&self) -> Self {
+        self.map_values(f64::tanh)
+    }
+
+    #[getter(to_dict)]
+    /// Returns the underlying dictionary, in key order. Nulls become `None`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"x": 42.0})
+    /// >>> d.to_dict
+    /// {'x': 42.0}
+    /// ```
+    fn py_to_dict(&self) -> IndexMap<String, Option<f64>> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.clone(), self.value_at(i)))
+            .collect()
+    }
+
+    /// `RedDict({'a': 1.0, 'b': 2.0}, len=2)`, eliding the middle entries of
+    /// dicts longer than the `repr_max_items` option.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 1.0, "b": None})
+    /// RedDict({'a': 1.0, 'b': None}, len=2)
+    /// ```
+    fn __repr__(&self, py: Python) -> PyResult<String> {
+        display::repr(py, self, options::repr_max_items())
+    }
+
+    /// A two-column table with aligned keys and values.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> print(rb.RedDict({"a": 1.0, "total": 250.5}))
+    /// a        1.0
+    /// total  250.5
+    /// ```
+    fn __str__(&self, py: Python) -> PyResult<String> {
+        display::table(py, self, options::repr_max_items())
+    }
+
+    /// HTML table used by Jupyter to display a RedDict.
+    fn _repr_html_(&self, py: Python) -> PyResult<String> {
+        display::html(py, self, options::repr_max_items())
+    }
+
+    fn __len__(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns the value stored under `key` (`None` if null), raising
+    /// `KeyError` if absent.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"x": 42.0})
+    /// >>> d["x"]
+    /// 42.0
+    /// ```
+    fn __getitem__(&self, key: &Bound<PyAny>) -> PyResult<Option<f64>> {
+        self.lookup(key)
+            .map(|i| self.value_at(i))
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))
+    }
+
+    fn __contains__(&self, key: &Bound<PyAny>) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    fn __iter__(&self) -> KeyIterator {
+        KeyIterator {
+            keys: Arc::clone(&self.keys),
+            pos: 0,
+        }
+    }
+
+    /// Returns the keys, ordered like `values()`.
+    fn keys(&self) -> Vec<&str> {
+        self.keys.iter().map(String::as_str).collect()
+    }
+
+    /// Returns the values, ordered like `keys()`.
+    #[pyo3(name = "values")]
+    fn py_values(&self) -> Vec<Option<f64>> {
+        (0..self.values.len()).map(|i| self.value_at(i)).collect()
+    }
+
+    /// Returns `(key, value)` pairs, ordered like `keys()`.
+    #[pyo3(name = "items")]
+    fn py_items(&self) -> Vec<(&str, Option<f64>)> {
+        self.keys
+            .iter()
+            .enumerate()
+            .map(|(i, k)| (k.as_str(), self.value_at(i)))
+            .collect()
+    }
+
+    /// Returns the value stored under `key`, or `default` if absent.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"x": 42.0})
+    /// >>> d.get("y", 0.0)
+    /// 0.0
+    /// ```
+    #[pyo3(signature = (key, default=None))]
+    fn get<'py>(
+        &self,
+        key: &Bound<'py, PyAny>,
+        default: Option<Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = key.py();
+        match self.lookup(key) {
+            Some(i) => Ok(self.value_at(i).into_pyobject(py)?.into_any()),
+            None => Ok(default.unwrap_or_else(|| py.None().into_bound(py))),
+        }
+    }
+
+    /// Value equality: the same keys mapped to the same values, regardless
+    /// of key order or internal layout. Nulls equal nulls, and unlike plain
+    /// floats a NaN equals a NaN, so every RedDict equals itself.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 1.0, "b": 2.0}) == rb.RedDict({"b": 2.0, "a": 1.0})
+    /// True
+    /// ```
+    fn __eq__(&self, other: &Bound<Self>) -> bool {
+        entries_match(self, &other.borrow(), |a, b| {
+            a == b || (a.is_nan() && b.is_nan())
+        })
+    }
+
+    /// Hash of a frozen RedDict, computed over its keys in sorted order and
+    /// the bit patterns of their values, so equal RedDicts hash equally
+    /// whatever their key order. Unfrozen RedDicts are unhashable.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0}).freeze()
+    /// >>> {d: "cached"}[rb.RedDict({"a": 1.0}).freeze()]
+    /// 'cached'
+    /// ```
+    fn __hash__(&self) -> PyResult<u64> {
+        if !self.frozen {
+            return Err(PyTypeError::new_err(
+                "unhashable type: 'RedDict' (use freeze() for a hashable copy)",
+            ));
+        }
+        let mut order: Vec<usize> = (0..self.keys.len()).collect();
+        order.sort_unstable_by_key(|&i| &self.keys[i]);
+
+        let mut hasher = DefaultHasher::new();
+        order.len().hash(&mut hasher);
+        for i in order {
+            self.keys[i].hash(&mut hasher);
+            // Equal values must hash equally: fold -0.0 into 0.0 and every
+            // NaN payload into one.
+            self.value_at(i)
+                .map(|v| match v {
+                    v if v.is_nan() => f64::NAN.to_bits(),
+                    0.0 => 0,
+                    v => v.to_bits(),
+                })
+                .hash(&mut hasher);
+        }
+        Ok(hasher.finish())
+    }
+
+    /// Whether `other` has the same keys, with nulls in the same places and
+    /// every other value within `atol + rtol * abs(other_value)`, like
+    /// `numpy.allclose`. NaNs only match when `equal_nan` is set.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d1 = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d2 = rb.RedDict({"a": 1.0 + 1e-9, "b": 2.0})
+    /// >>> d1 == d2, d1.allclose(d2)
+    /// (False, True)
+    /// ```
+    #[pyo3(signature = (other, rtol=1e-5, atol=1e-8, equal_nan=false))]
+    fn allclose(&self, other: &Bound<Self>, rtol: f64, atol: f64, equal_nan: bool) -> bool {
+        entries_match(self, &other.borrow(), |a, b| {
+            a == b
+                || (a - b).abs() <= atol + rtol * b.abs()
+                || (equal_nan && a.is_nan() && b.is_nan())
+        })
+    }
+
+    /// Returns a frozen RedDict sharing this one's storage. Frozen RedDicts
+    /// are hashable and refuse every mutation with `TypeError`; results of
+    /// operations take the frozen flag of their left operand, and `d += x`
+    /// rebinds `d` to such a result as it would for a tuple.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0}).freeze()
+    /// >>> d.frozen, (d + 1.0).frozen
+    /// (True, True)
+    /// >>> len({d, rb.RedDict({"a": 1.0}).freeze()})
+    /// 1
+    /// ```
+    #[must_use]
+    fn freeze(&self) -> Self {
+        Self {
+            frozen: true,
+            ..self.clone()
+        }
+    }
+
+    /// Whether this RedDict is frozen; see `freeze`.
+    #[getter]
+    fn frozen(&self) -> bool {
+        self.frozen
+    }
+
+    /// Pickle support. The state is the key tuple, the values packed as
+    /// little-endian `f64` bytes, the validity mask as bytes (or `None`) and
+    /// the frozen flag, which avoids building an intermediate Python dict.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import pickle
+    /// >>> d = rb.RedDict({"a": 1.0, "b": None})
+    /// >>> pickle.loads(pickle.dumps(d)) == d
+    /// True
+    /// ```
+    #[allow(clippy::type_complexity)]
+    fn __reduce__<'py>(
+        slf: &Bound<'py, Self>,
+    ) -> PyResult<(
+        Bound<'py, PyAny>,
+        (
+            Bound<'py, PyTuple>,
+            Bound<'py, PyBytes>,
+            Option<Bound<'py, PyBytes>>,
+            bool,
+        ),
+    )> {
+        let py = slf.py();
+        let this = slf.borrow();
+        let values: Vec<u8> = this.values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        let state = (
+            PyTuple::new(py, this.keys.iter())?,
+            PyBytes::new(py, &values),
+            this.validity
+                .as_ref()
+                .map(|validity| PyBytes::new(py, &validity.to_bytes())),
+            this.frozen,
+        );
+        Ok((slf.get_type().getattr("_from_state")?, state))
+    }
+
+    /// Rebuilds a pickled RedDict. Its key layout is interned, so dicts
+    /// unpickled from the same source share it and operate on the fast path.
+    #[staticmethod]
+    fn _from_state(
+        keys: Vec<String>,
+        values: &[u8],
+        validity: Option<&[u8]>,
+        frozen: bool,
+    ) -> PyResult<Self> {
+        if values.len() != keys.len() * 8 {
+            return Err(PyValueError::new_err(format!(
+                "expected {} bytes of values for {} keys, got {}",
+                keys.len() * 8,
+                keys.len(),
+                values.len()
+            )));
+        }
+        let validity = match validity {
+            Some(bytes) => Bitmap::from_bytes(bytes, keys.len())
+                .ok_or_else(|| PyValueError::new_err("validity mask is too short"))?,
+            None => Bitmap::new_valid(keys.len()),
+        };
+        let values = values
+            .chunks_exact(8)
+            .map(|chunk| f64::from_le_bytes(chunk.try_into().expect("chunks are 8 bytes")))
+            .collect();
+        let schema = KeySchema::new(keys)?;
+        Ok(Self {
+            keys: schema.keys,
+            index: schema.index,
+            values: Arc::new(values),
+            validity: None,
+            frozen,
+        }
+        .with_validity(validity))
+    }
+
+    /// Serializes this RedDict in the redbear binary format: a versioned
+    /// header, the key table, the values as little-endian `f64` and, unless
+    /// `checksum=False`, a CRC-32 trailer. See `to_bytes_many` for writing
+    /// several RedDicts with one key table.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": None})
+    /// >>> rb.RedDict.from_bytes(d.to_bytes()) == d
+    /// True
+    /// ```
+    #[pyo3(signature = (checksum=true))]
+    fn to_bytes<'py>(&self, py: Python<'py>, checksum: bool) -> PyResult<Bound<'py, PyBytes>> {
+        let bytes = format::encode(py, std::slice::from_ref(self), checksum)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Reads a RedDict written by `to_bytes`. Raises `ValueError` if the
+    /// data is corrupt, fails its checksum or holds other than one RedDict.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        Self::single(format::decode(data)?)
+    }
+
+    /// Writes `to_bytes(checksum)` to the file at `path`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0})
+    /// >>> d.save("weights.rbd")
+    /// >>> rb.RedDict.load("weights.rbd").to_dict
+    /// {'a': 1.0}
+    /// ```
+    #[pyo3(signature = (path, checksum=true))]
+    fn save(&self, py: Python<'_>, path: PathBuf, checksum: bool) -> PyResult<()> {
+        std::fs::write(
+            path,
+            format::encode(py, std::slice::from_ref(self), checksum)?,
+        )?;
+        Ok(())
+    }
+
+    /// Reads a RedDict from a file written by `save`.
+    #[staticmethod]
+    fn load(path: PathBuf) -> PyResult<Self> {
+        Self::single(format::decode(&std::fs::read(path)?)?)
+    }
+
+    /// Exports the values, in key order, as a read-only one-dimensional
+    /// `f64` buffer, so `memoryview(d)` and `numpy.asarray(d)` share them
+    /// without copying. Nulls read as NaN.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> memoryview(rb.RedDict({"a": 1.0, "b": 2.0})).tolist()
+    /// [1.0, 2.0]
+    /// ```
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        // SAFETY: `view` comes straight from the interpreter.
+        unsafe { array::fill_buffer(view, flags, &slf) }
+    }
+
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        // SAFETY: the interpreter only releases views `__getbuffer__` filled.
+        unsafe { array::release_buffer(view) }
+    }
+
+    /// Returns the values as a numpy array in key order. Without `copy=True`
+    /// or a `dtype` other than float64 the array is a read-only view of the
+    /// RedDict's storage. As in NumPy 2, `copy=False` raises `ValueError`
+    /// when `dtype` would need a copy.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import numpy as np
+    /// >>> np.asarray(rb.RedDict({"b": 2.0, "a": 1.0}))
+    /// array([2., 1.])
+    /// ```
+    #[pyo3(signature = (dtype=None, copy=None))]
+    fn __array__<'py>(
+        slf: &Bound<'py, Self>,
+        dtype: Option<&Bound<'py, PyAny>>,
+        copy: Option<bool>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let py = slf.py();
+        let numpy = py.import("numpy")?;
+        let kwargs = PyDict::new(py);
+        kwargs.set_item("dtype", "float64")?;
+        let array = numpy.call_method("frombuffer", (slf,), Some(&kwargs))?;
+        let dtype = match dtype {
+            Some(dtype) => Some(numpy.call_method1("dtype", (dtype,))?),
+            None => None,
+        };
+        let converts = match &dtype {
+            Some(dtype) => !dtype.eq(array.getattr("dtype")?)?,
+            None => false,
+        };
+        match (copy, dtype) {
+            (Some(false), Some(_)) if converts => Err(PyValueError::new_err(
+                "unable to avoid a copy when converting a RedDict to another dtype",
+            )),
+            (Some(true), Some(dtype)) => array.call_method1("astype", (dtype,)),
+            (_, Some(dtype)) if converts => array.call_method1("astype", (dtype,)),
+            (Some(true), None) => array.call_method0("copy"),
+            _ => Ok(array),
+        }
+    }
+
+    /// numpy ufunc support: `np.sqrt(d)`, `np.add(d, 3.0)`,
+    /// `np.maximum(d1, d2)` and the like return RedDicts. Unary ufuncs keep
+    /// the input's layout; binary ones align a second RedDict like the
+    /// operators do, with its missing keys becoming nulls. `out=` takes a
+    /// RedDict holding the result's keys, which is overwritten in its own
+    /// key order; other keys raise `ValueError`.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import numpy as np
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 4.0})
+    /// >>> np.sqrt(d).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// >>> np.maximum(d, rb.RedDict({"b": 5.0, "a": 0.0})).to_dict
+    /// {'a': 1.0, 'b': 5.0}
+    /// >>> _ = np.add(d, 1.0, out=d)
+    /// >>> d.to_dict
+    /// {'a': 2.0, 'b': 5.0}
+    /// ```
+    #[pyo3(signature = (ufunc, method, *inputs, **kwargs))]
+    fn __array_ufunc__<'py>(
+        _slf: &Bound<'py, Self>,
+        ufunc: &Bound<'py, PyAny>,
+        method: &str,
+        inputs: &Bound<'py, PyTuple>,
+        kwargs: Option<&Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        ufunc::apply(ufunc, method, inputs, kwargs)
+    }
+
+    /// Returns the keys as a numpy array, in the same order as `__array__`.
+    /// The default string (`U`) dtype is the one `from_arrays` reads straight
+    /// from the buffer; pass `dtype=object` for an array of Python strings.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"b": 2.0, "a": 1.0}).keys_array()
+    /// array(['b', 'a'], dtype='<U1')
+    /// ```
+    #[pyo3(signature = (dtype=None))]
+    fn keys_array<'py>(
+        &self,
+        py: Python<'py>,
+        dtype: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let numpy = py.import("numpy")?;
+        let kwargs = PyDict::new(py);
+        match dtype {
+            Some(dtype) => kwargs.set_item("dtype", dtype)?,
+            // `str` rather than no dtype, so that no keys still give `U`.
+            None => kwargs.set_item("dtype", "str")?,
+        }
+        numpy.call_method("array", (self.keys(),), Some(&kwargs))
+    }
+
+    /// Builds a RedDict from parallel sequences of keys and values, in that
+    /// order. numpy string (`U`) and float arrays are read directly from
+    /// their buffers; other sequences work too, with `None` values as nulls.
+    ///
+    /// Dicts built from equal key sequences share one key layout.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import numpy as np
+    /// >>> rb.RedDict.from_arrays(np.array(["a", "b"]), np.array([1.0, 2.0])).to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// ```
+    #[staticmethod]
+    fn from_arrays(keys: &Bound<PyAny>, values: &Bound<PyAny>) -> PyResult<Self> {
+        let keys = array::extract_keys(keys)?;
+        let (values, validity) = array::extract_values(values)?;
+        if keys.len() != values.len() {
+            return Err(PyValueError::new_err(format!(
+                "got {} keys but {} values",
+                keys.len(),
+                values.len()
+            )));
+        }
+        let schema = KeySchema::new(keys)?;
+        Ok(Self {
+            keys: schema.keys,
+            index: schema.index,
+            values: Arc::new(values),
+            validity: None,
+            frozen: false,
+        }
+        .with_validity(validity))
+    }
+
+    /// Exports this RedDict as an Arrow record batch with a `key` (utf8) and
+    /// a nullable `value` (float64) column, through the Arrow PyCapsule
+    /// interface. The values are shared with the consumer, not copied.
+    ///
+    /// `requested_schema` is accepted as the protocol requires but not
+    /// applied; consumers cast the batch if they need other types.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import pyarrow as pa
+    /// >>> pa.record_batch(rb.RedDict({"a": 1.0, "b": None})).to_pydict()
+    /// {'key': ['a', 'b'], 'value': [1.0, None]}
+    /// ```
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_array__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyTuple>> {
+        let _ = requested_schema;
+        arrow::array_capsules(py, self)
+    }
+
+    /// The same record batch as `__arrow_c_array__`, as a one-batch Arrow
+    /// stream, which is what polars and DuckDB read.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import polars as pl
+    /// >>> pl.DataFrame(rb.RedDict({"a": 1.0})).to_dicts()
+    /// [{'key': 'a', 'value': 1.0}]
+    /// ```
+    #[pyo3(signature = (requested_schema=None))]
+    fn __arrow_c_stream__<'py>(
+        &self,
+        py: Python<'py>,
+        requested_schema: Option<&Bound<'py, PyAny>>,
+    ) -> PyResult<Bound<'py, PyCapsule>> {
+        let _ = requested_schema;
+        arrow::stream_capsule(py, self)
+    }
+
+    /// Builds a RedDict from any object implementing the Arrow PyCapsule
+    /// interface (a pyarrow table or record batch, a polars DataFrame, ...).
+    /// Keys come from its `key` column and values from its `value` column,
+    /// or from its first and second column when it has exactly two; null
+    /// values become nulls. pyarrow is not needed.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import pyarrow as pa
+    /// >>> table = pa.table({"key": ["a", "b"], "value": [1.0, None]})
+    /// >>> rb.RedDict.from_arrow(table).to_dict
+    /// {'a': 1.0, 'b': None}
+    /// ```
+    #[staticmethod]
+    fn from_arrow(obj: &Bound<PyAny>) -> PyResult<Self> {
+        arrow::from_arrow(obj)
+    }
+
+    /// Returns a pandas Series of the values indexed by key, in key order.
+    /// Nulls become NaN.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"b": 2.0, "a": None}).to_pandas()
+    /// b    2.0
+    /// a    NaN
+    /// dtype: float64
+    /// ```
+    fn to_pandas<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        frames::to_pandas(slf)
+    }
+
+    /// Builds a RedDict from a pandas Series, keyed by its index. Labels
+    /// must be unique strings; missing values become nulls.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import pandas as pd
+    /// >>> rb.RedDict.from_pandas(pd.Series([1.0, None], index=["a", "b"])).to_dict
+    /// {'a': 1.0, 'b': None}
+    /// >>> rb.RedDict.from_pandas(pd.Series([1.0, 2.0], index=["a", "a"]))
+    /// Traceback (most recent call last):
+    /// ...
+    /// ValueError: duplicate index label 'a'
+    /// ```
+    #[staticmethod]
+    fn from_pandas(series: &Bound<PyAny>) -> PyResult<Self> {
+        frames::from_pandas(series)
+    }
+
+    /// Returns a polars DataFrame with `key` and `value` columns, in key
+    /// order, built from the Arrow export.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> rb.RedDict({"a": 1.0, "b": None}).to_polars().to_dicts()
+    /// [{'key': 'a', 'value': 1.0}, {'key': 'b', 'value': None}]
+    /// ```
+    fn to_polars<'py>(slf: &Bound<'py, Self>) -> PyResult<Bound<'py, PyAny>> {
+        frames::to_polars(slf)
+    }
+
+    /// Builds a RedDict from two columns of a polars DataFrame. Keys must
+    /// be unique, non-null strings; values are cast to Float64 and nulls
+    /// stay nulls.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> import polars as pl
+    /// >>> df = pl.DataFrame({"name": ["a", "b"], "score": [1, 2]})
+    /// >>> rb.RedDict.from_polars(df, "name", "score").to_dict
+    /// {'a': 1.0, 'b': 2.0}
+    /// ```
+    #[staticmethod]
+    #[pyo3(signature = (df, key_col="key", value_col="value"))]
+    fn from_polars(df: &Bound<PyAny>, key_col: &str, value_col: &str) -> PyResult<Self> {
+        frames::from_polars(df, key_col, value_col)
+    }
+
+    /// A shallow copy. Storage is shared copy-on-write, so this costs a few
+    /// reference count increments; frozen RedDicts return themselves.
+    fn __copy__(slf: &Bound<Self>) -> PyResult<Py<Self>> {
+        let this = slf.borrow();
+        if this.frozen {
+            return Ok(slf.clone().unbind());
+        }
+        Py::new(slf.py(), this.clone())
+    }
+
+    /// The same as `__copy__`: keys and values are immutable, and mutation
+    /// copies any shared storage first, so sharing it is already a deep copy.
+    fn __deepcopy__(slf: &Bound<Self>, _memo: &Bound<PyAny>) -> PyResult<Py<Self>> {
+        Self::__copy__(slf)
+    }
+
+    /// Sets the value stored under `key`, appending the key if it is new.
+    /// `None` stores a null.
+    ///
+    /// Like every mutation, this copies any storage still shared with other
+    /// RedDicts (or a `KeySchema`) first, so they are unaffected.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0})
+    /// >>> d["a"] = 5.0
+    /// >>> d["b"] = None
+    /// >>> d.to_dict
+    /// {'a': 5.0, 'b': None}
+    /// ```
+    fn __setitem__(&mut self, key: String, value: Option<f64>) -> PyResult<()> {
+        self.check_mutable()?;
+        self.insert(key, value);
+        Ok(())
+    }
+
+    /// Removes `key`, raising `KeyError` if absent. Later keys move down one
+    /// position, which takes time linear in the number of keys.
+    fn __delitem__(&mut self, key: &Bound<PyAny>) -> PyResult<()> {
+        self.check_mutable()?;
+        let pos = self
+            .lookup(key)
+            .ok_or_else(|| PyKeyError::new_err(key.clone().unbind()))?;
+        self.remove_at(pos);
+        Ok(())
+    }
+
+    /// Removes `key` and returns its value. Returns `default` if the key is
+    /// absent and a default was given, and raises `KeyError` otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d.pop("a")
+    /// 1.0
+    /// >>> d.pop("z", 0.0)
+    /// 0.0
+    /// >>> d.to_dict
+    /// {'b': 2.0}
+    /// ```
+    #[pyo3(signature = (key, *default))]
+    fn pop<'py>(
+        &mut self,
+        key: &Bound<'py, PyAny>,
+        default: &Bound<'py, PyTuple>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        self.check_mutable()?;
+        let py = key.py();
+        match (self.lookup(key), default.len()) {
+            (Some(pos), _) => Ok(self.remove_at(pos).into_pyobject(py)?.into_any()),
+            (None, 0) => Err(PyKeyError::new_err(key.clone().unbind())),
+            (None, 1) => default.get_item(0),
+            (None, n) => Err(PyTypeError::new_err(format!(
+                "pop expected at most 2 arguments, got {}",
+                n + 1
+            ))),
+        }
+    }
+
+    /// Returns the value under `key`, first inserting `default` if the key is
+    /// absent.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0})
+    /// >>> d.setdefault("a", 9.0), d.setdefault("b", 9.0)
+    /// (1.0, 9.0)
+    /// ```
+    #[pyo3(signature = (key, default=None))]
+    fn setdefault(&mut self, key: String, default: Option<f64>) -> PyResult<Option<f64>> {
+        if let Some(&pos) = self.index.get(&key) {
+            return Ok(self.value_at(pos));
+        }
+        self.check_mutable()?;
+        self.insert(key, default);
+        Ok(default)
+    }
+
+    /// Sets every entry of `other` (a RedDict, a mapping or an iterable of
+    /// key/value pairs) and of `kwargs`, like `dict.update`.
+    ///
+    /// Updating from a RedDict with the same key layout replaces the values
+    /// wholesale and shares the other dict's storage instead of copying it.
+    ///
+    /// # Examples
+    ///
+    /// ```python
+    /// >>> d = rb.RedDict({"a": 1.0, "b": 2.0})
+    /// >>> d.update(rb.RedDict({"b": 20.0, "c": 30.0}), a=10.0)
+    /// >>> d.to_dict
+    /// {'a': 10.0, 'b': 20.0, 'c': 30.0}
+    /// ```
+    #[pyo3(signature = (other=None, **kwargs))]
+    fn update(
+        slf: &Bound<Self>,
+        other: Option<&Bound<PyAny>>,
+        kwargs: Option<&Bound<PyDict>>,
+    ) -> PyResult<()> {
+        slf.borrow().check_mutable()?;
+        if let Some(other) = other {
+            if let Ok(other) = other.cast::<RedDict>() {
+                // Clone first so `d.update(d)` does not borrow `d` twice.
+                let other = other.borrow().clone();
+                slf.borrow_mut().update_from(&other);
+            } else if other.hasattr("keys")? {
+                for key in other.call_method0("keys")?.try_iter()? {
+                    let key = key?;
+                    let value = other.get_item(&key)?;
+                    slf.borrow_mut().insert(key.extract()?, value.extract()?);
+                }
+            } else {
+                for pair in other.try_iter()? {
+                    let (key, value): (String, Option<f64>) = pair?.extract()?;
+                    slf.borrow_mut().insert(key, value);
+                }
+            }
+        }
+        if let Some(kwargs) = kwargs {
+            let mut this = slf.borrow_mut();
+            for (key, value) in kwargs.iter() {
+                this.insert(key.extract()?, value.extract()?);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes every entry.
+    fn clear(&mut self) -> PyResult<()> {
+        self.check_mutable()?;
+        self.keys = Arc::default();
+        self.index = Arc::default();
+        self.values = Arc::default();
+        self.validity = None;
+        Ok(())
+    }
+
+    fn __add__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), Some(0.0), |a, b| a + b)?
+            }
+            Operand::Scalar(s) => self.add_scalar(s),
+        })
+    }
+
+    fn __radd__(&self, other: Operand) -> PyResult<Self> {
+        self.__add__(other)
+    }
+
+    fn __sub__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), Some(0.0), |a, b| a - b)?
+            }
+            Operand::Scalar(s) => self.subtract_scalar(s),
+        })
+    }
+
+    fn __rsub__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => o
+                .borrow()
+                .merge_operator(o.py(), self, Some(0.0), |a, b| a - b)?,
+            Operand::Scalar(s) => self.map_values(|v| s - v),
+        })
+    }
+
+    fn __mul__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), Some(1.0), |a, b| a * b)?
+            }
+            Operand::Scalar(s) => self.multiply_scalar(s),
+        })
+    }
+
+    fn __rmul__(&self, other: Operand) -> PyResult<Self> {
+        self.__mul__(other)
+    }
+
+    fn __truediv__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), Some(1.0), |a, b| a / b)?
+            }
+            Operand::Scalar(s) => self.divide_scalar(s),
+        })
+    }
+
+    fn __rtruediv__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => o
+                .borrow()
+                .merge_operator(o.py(), self, Some(1.0), |a, b| a / b)?,
+            Operand::Scalar(s) => self.map_values(|v| s / v),
+        })
+    }
+
+    fn __pow__(&self, other: Operand, modulo: Option<&Bound<PyAny>>) -> PyResult<Self> {
+        check_no_modulo(modulo)?;
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), Some(1.0), |a, b| a.powf(*b))?
+            }
+            Operand::Scalar(s) => self.power_scalar(s),
+        })
+    }
+
+    fn __rpow__(&self, other: Operand, modulo: Option<&Bound<PyAny>>) -> PyResult<Self> {
+        check_no_modulo(modulo)?;
+        Ok(match other {
+            Operand::Dict(o) => o
+                .borrow()
+                .merge_operator(o.py(), self, Some(1.0), |a, b| a.powf(*b))?,
+            Operand::Scalar(s) => self.map_values(|v| s.powf(v)),
+        })
+    }
+
+    fn __mod__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), None, |a, b| ops::py_mod(*a, *b))?
+            }
+            Operand::Scalar(s) => self.modulo_scalar(s),
+        })
+    }
+
+    fn __rmod__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => o
+                .borrow()
+                .merge_operator(o.py(), self, None, |a, b| ops::py_mod(*a, *b))?,
+            Operand::Scalar(s) => self.map_values(|v| ops::py_mod(s, v)),
+        })
+    }
+
+    fn __floordiv__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => {
+                self.merge_operator(o.py(), &o.borrow(), None, |a, b| ops::py_floor_div(*a, *b))?
+            }
+            Operand::Scalar(s) => self.floor_divide_scalar(s),
+        })
+    }
+
+    fn __rfloordiv__(&self, other: Operand) -> PyResult<Self> {
+        Ok(match other {
+            Operand::Dict(o) => o
+                .borrow()
+                .merge_operator(o.py(), self, None, |a, b| ops::py_floor_div(*a, *b))?,
+            Operand::Scalar(s) => self.map_values(|v| ops::py_floor_div(s, v)),
+        })
+    }
+
+    fn __neg__(&self) -> Self {
+        self.neg()
+    }
+
+    fn __abs__(&self) -> Self {
+        self.abs()
+    }
+
+    // The augmented assignments update `values` in place, like the `i*`
+    // methods, and see the same keys as the plain operators. On a frozen
+    // RedDict they return `NotImplemented`, so `d += x` falls back to
+    // `d = d + x` and rebinds the name, as it does for a tuple.
+
+    fn __iadd__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, Some(0.0), |a, b| a + b)
+    }
+
+    fn __isub__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, Some(0.0), |a, b| a - b)
+    }
+
+    fn __imul__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, Some(1.0), |a, b| a * b)
+    }
+
+    fn __itruediv__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, Some(1.0), |a, b| a / b)
+    }
+
+    fn __imod__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, None, ops::py_mod)
+    }
+
+    fn __ifloordiv__(slf: Unfrozen, other: Operand) -> PyResult<()> {
+        Self::update_operator(&slf, other, None, ops::py_floor_div)
+    }
+
+    fn __ipow__(slf: Unfrozen, other: Operand, modulo: Option<&Bound<PyAny>>) -> PyResult<()> {
+        check_no_modulo(modulo)?;
+        Self::update_operator(&slf, other, Some(1.0), f64::powf)
+    }
+}
+
+impl RedDict {
+    /// The only RedDict in a decoded blob.
+    fn single(mut dicts: Vec<Self>) -> PyResult<Self> {
+        if dicts.len() != 1 {
+            return Err(PyValueError::new_err(format!(
+                "expected one RedDict, found {}; use from_bytes_many",
+                dicts.len()
+            )));
+        }
+        Ok(dicts.remove(0))
+    }
+
+    /// Builds a `RedDict` on `schema`'s layout, reordering the values of
+    /// `dicts`; a key in a later dict overrides an earlier one.
+    fn with_schema<'a, 'py: 'a>(
+        dicts: impl Iterator<Item = &'a Bound<'py, PyDict>>,
+        schema: &KeySchema,
+    ) -> PyResult<Self> {
+        let mut values = vec![f64::NAN; schema.keys.len()];
+        let mut validity = Bitmap::new_valid(schema.keys.len());
+        let mut seen = vec![false; schema.keys.len()];
+        for (k, v) in dicts.flat_map(|d| d.iter()) {
+            let k: String = k.extract()?;
+            let Some(&pos) = schema.index.get(&k) else {
+                return Err(PyValueError::new_err(format!(
+                    "key {k:?} is not in the schema"
+                )));
+            };
+            let v: Option<f64> = v.extract()?;
+            values[pos] = v.unwrap_or(f64::NAN);
+            validity.set(pos, v.is_some());
+            seen[pos] = true;
+        }
+        if let Some(missing) = seen.iter().position(|&filled| !filled) {
+            return Err(PyValueError::new_err(format!(
+                "key {:?} from the schema is missing",
+                schema.keys[missing]
+            )));
+        }
+
+        Ok(Self {
+            keys: Arc::clone(&schema.keys),
+            index: Arc::clone(&schema.index),
+            values: Arc::new(values),
+            validity: None,
+            frozen: false,
+        }
+        .with_validity(validity))
+    }
+
+    /// Installs `validity` as the null mask, dropping it when nothing is null
+    /// and forcing null slots to NaN otherwise.
+    fn with_validity(mut self, validity: Bitmap) -> Self {
+        self.set_validity(validity);
+        self
+    }
+
+    fn set_validity(&mut self, validity: Bitmap) {
+        if validity.count_unset() == 0 {
+            self.validity = None;
+        } else {
+            self.validity = Some(Arc::new(validity));
+            self.mask_nulls();
+        }
+    }
+
+    /// Resets null slots to NaN after an operation that may have changed them.
+    fn mask_nulls(&mut self) {
+        if let Some(validity) = &self.validity {
+            for (i, val) in Arc::make_mut(&mut self.values).iter_mut().enumerate() {
+                if !validity.get(i) {
+                    *val = f64::NAN;
+                }
+            }
+        }
+    }
+
+    fn is_valid(&self, i: usize) -> bool {
+        self.validity.as_ref().is_none_or(|v| v.get(i))
+    }
+
+    /// The value at position `i`, or `None` if it is null.
+    fn value_at(&self, i: usize) -> Option<f64> {
+        self.is_valid(i).then(|| self.values[i])
+    }
+
+    /// Runs a reduction over the non-null values, also leaving out NaN when
+    /// `skipnan` is set. With `skipna` unset, any null makes the result `None`
+    /// instead.
+    fn aggregate<T, F>(&self, skipna: bool, skipnan: bool, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = f64>) -> T,
+    {
+        self.aggregate_indexed(skipna, skipnan, |entries| f(&mut entries.map(|(_, v)| v)))
+    }
+
+    /// `aggregate` over `(position, value)` pairs.
+    fn aggregate_indexed<T, F>(&self, skipna: bool, skipnan: bool, f: F) -> Option<T>
+    where
+        F: FnOnce(&mut dyn Iterator<Item = (usize, f64)>) -> T,
+    {
+        if !skipna && self.validity.is_some() {
+            return None;
+        }
+        let mut entries = self
+            .values
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|&(i, v)| self.is_valid(i) && !(skipnan && v.is_nan()));
+        Some(f(&mut entries))
+    }
+
+    /// The first smallest (or largest, depending on `better`) entry, with
+    /// `Some(None)` meaning there were no values.
+    fn extreme<F>(&self, skipna: bool, skipnan: bool, better: F) -> Option<Option<(usize, f64)>>
+    where
+        F: Fn(f64, f64) -> bool,
+    {
+        self.aggregate_indexed(skipna, skipnan, |entries| {
+            stats::arg_extreme(entries, better)
+        })
+    }
+
+    fn arg_extreme<F>(
+        &self,
+        name: &str,
+        skipna: bool,
+        skipnan: bool,
+        better: F,
+    ) -> PyResult<Option<&str>>
+    where
+        F: Fn(f64, f64) -> bool,
+    {
+        match self.extreme(skipna, skipnan, better) {
+            None => Ok(None),
+            Some(None) => Err(PyValueError::new_err(format!(
+                "{name} of a RedDict with no values"
+            ))),
+            Some(Some((i, _))) => Ok(Some(self.keys[i].as_str())),
+        }
+    }
+
+    /// Looks up a Python key's position; non-string keys are simply absent.
+    fn lookup(&self, key: &Bound<PyAny>) -> Option<usize> {
+        let key = key.cast::<PyString>().ok()?.to_str().ok()?;
+        self.index.get(key).copied()
+    }
+
+    /// Sets `key` to `value`, appending it if new.
+    fn insert(&mut self, key: String, value: Option<f64>) {
+        if let Some(&pos) = self.index.get(&key) {
+            self.set_at(pos, value);
+            return;
+        }
+        let pos = self.values.len();
+        Arc::make_mut(&mut self.index).insert(key.clone(), pos);
+        Arc::make_mut(&mut self.keys).push(key);
+        Arc::make_mut(&mut self.values).push(f64::NAN);
+        if let Some(validity) = &mut self.validity {
+            Arc::make_mut(validity).push(true);
+        }
+        self.set_at(pos, value);
+    }
+
+    /// Overwrites the entry at `pos`, keeping the null mask in step.
+    fn set_at(&mut self, pos: usize, value: Option<f64>) {
+        Arc::make_mut(&mut self.values)[pos] = value.unwrap_or(f64::NAN);
+        match (value, &mut self.validity) {
+            (Some(_), None) => {}
+            (Some(_), Some(validity)) => {
+                let validity = Arc::make_mut(validity);
+                validity.set(pos, true);
+                if validity.count_unset() == 0 {
+                    self.validity = None;
+                }
+            }
+            (None, validity) => {
+                let len = self.values.len();
+                let validity = validity.get_or_insert_with(|| Arc::new(Bitmap::new_valid(len)));
+                Arc::make_mut(validity).set(pos, false);
+            }
+        }
+    }
+
+    /// Removes the entry at `pos`, returning its value. Keys after it move
+    /// down one position so the index stays dense and in insertion order.
+    fn remove_at(&mut self, pos: usize) -> Option<f64> {
+        let value = self.value_at(pos);
+        let keys = Arc::make_mut(&mut self.keys);
+        let index = Arc::make_mut(&mut self.index);
+        let key = keys.remove(pos);
+        index.remove(&key);
+        for key in &keys[pos..] {
+            if let Some(i) = index.get_mut(key) {
+                *i -= 1;
+            }
+        }
+        Arc::make_mut(&mut self.values).remove(pos);
+        if let Some(validity) = &mut self.validity {
+            let validity = Arc::make_mut(validity);
+            validity.remove(pos);
+            if validity.count_unset() == 0 {
+                self.validity = None;
+            }
+        }
+        value
+    }
+
+    /// `update` from another RedDict.
+    fn update_from(&mut self, other: &RedDict) {
+        if let Alignment::Identical = align(&self.index, &other.index) {
+            self.values = Arc::clone(&other.values);
+            self.validity = other.validity.clone();
+            return;
+        }
+        for (pos, key) in other.keys.iter().enumerate() {
+            self.insert(key.clone(), other.value_at(pos));
+        }
+    }
+
+    /// `merge` for the operator protocol, which cannot take keyword arguments
+    /// and so always follows the module-level `strict` default.
+    fn merge_operator<F>(
+        &self,
+        py: Python<'_>,
+        other: &RedDict,
+        fill: Option<f64>,
+        f: F,
+    ) -> PyResult<Self>
+    where
+        F: Fn(&f64, &f64) -> f64,
+    {
+        check_keys(py, self, other, options::strict())?;
+        Ok(merge(self, other, fill, f))
+    }
+
+    /// Shared body of the binary methods taking another RedDict.
+    fn binary<F>(&self, other: &Bound<Self>, args: JoinArgs, f: F) -> PyResult<Self>
+    where
+        F: Fn(&f64, &f64) -> f64,
+    {
+        let other_ref = other.borrow();
+        check_keys(other.py(), self, &other_ref, args.strict)?;
+        Ok(join(
+            self,
+            &other_ref,
+            args.how,
+            args.fill_left,
+            args.fill_right,
+            f,
+        ))
+    }
+
+    /// Applies `f` to every value in place, copying the buffer first if it
+    /// is shared.
+    fn map_values_in_place<F>(&mut self, f: F)
+    where
+        F: Fn(f64) -> f64,
+    {
+        Arc::make_mut(&mut self.values)
+            .iter_mut()
+            .for_each(|val| *val = f(*val));
+        self.mask_nulls();
+    }
+
+    /// Shared body of the in-place binary methods. `other` is cloned (which
+    /// only copies `Arc`s) before `slf` is borrowed mutably, so passing the
+    /// same RedDict on both sides works.
+    fn update_in_place<F>(
+        slf: &Bound<Self>,
+        other: &Bound<Self>,
+        fill: Option<f64>,
+        strict: bool,
+        f: F,
+    ) -> PyResult<()>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        let other = other.borrow().clone();
+        let mut this = slf.borrow_mut();
+        this.check_mutable()?;
+        check_keys(slf.py(), &this, &other, strict)?;
+        merge_into(&mut this, &other, fill, |a, b| f(*a, *b));
+        Ok(())
+    }
+
+    /// Shared body of the augmented assignment operators.
+    fn update_operator<F>(
+        slf: &Bound<Self>,
+        other: Operand,
+        fill: Option<f64>,
+        f: F,
+    ) -> PyResult<()>
+    where
+        F: Fn(f64, f64) -> f64,
+    {
+        match other {
+            Operand::Dict(o) => Self::update_in_place(slf, &o, fill, options::strict(), f),
+            Operand::Scalar(s) => {
+                let mut this = slf.borrow_mut();
+                this.check_mutable()?;
+                this.map_values_in_place(|v| f(v, s));
+                Ok(())
+            }
+        }
+    }
+
+    /// Raises `TypeError` if the RedDict is frozen.
+    fn check_mutable(&self) -> PyResult<()> {
+        if self.frozen {
+            return Err(PyTypeError::new_err("RedDict is frozen"));
+        }
+        Ok(())
+    }
+
+    /// Returns a copy with `f` applied to every value, sharing the index.
+    fn map_values<F>(&self, f: F) -> Self
+    where
+        F: Fn(f64) -> f64,
+    {
+        // Collect into a fresh buffer rather than `make_mut`, which would
+        // first copy the shared values and then overwrite them. `keys`,
+        // `index` and `validity` stay shared with `self`.
+        let mut new = RedDict {
+            values: Arc::new(self.values.iter().map(|&v| f(v)).collect()),
+            ..self.clone()
+        };
+        new.mask_nulls();
+        new
+    }
+}
+
+/// Iterator over the keys of a `RedDict`, in key order.
+///
+/// Holds its own reference to the key vector, so the dict it came from can be
+/// replaced (e.g. by `+=`) while iterating.
+#[pyclass]
+struct KeyIterator {
+    keys: Arc<Vec<String>>,
+    pos: usize,
+}
+
+#[pymethods]
+impl KeyIterator {
+    fn __iter__(slf: PyRef<Self>) -> PyRef<Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<String> {
+        let key = self.keys.get(self.pos)?.clone();
+        self.pos += 1;
+        Some(key)
+    }
+}
+
+/// Right-hand side of a Python operator: another `RedDict` or a number.
+///
+/// Anything else fails extraction, which makes PyO3 return `NotImplemented`.
+#[derive(FromPyObject)]
+enum Operand<'py> {
+    Dict(Bound<'py, RedDict>),
+    Scalar(f64),
+}
+
+/// Receiver of the augmented assignments: a RedDict that is not frozen.
+///
+/// Extraction fails on a frozen RedDict, which makes PyO3 return
+/// `NotImplemented` so Python falls back to the plain operator.
+struct Unfrozen<'a, 'py>(&'a Bound<'py, RedDict>);
+
+impl<'a, 'py> TryFrom<BoundRef<'a, 'py, RedDict>> for Unfrozen<'a, 'py> {
+    type Error = Frozen;
+
+    fn try_from(slf: BoundRef<'a, 'py, RedDict>) -> Result<Self, Frozen> {
+        if slf.0.borrow().frozen {
+            return Err(Frozen);
+        }
+        Ok(Unfrozen(slf.0))
+    }
+}
+
+/// Why a RedDict is not `Unfrozen`.
+struct Frozen;
+
+impl From<Frozen> for PyErr {
+    fn from(_: Frozen) -> Self {
+        PyTypeError::new_err("RedDict is frozen")
+    }
+}
+
+impl<'py> std::ops::Deref for Unfrozen<'_, 'py> {
+    type Target = Bound<'py, RedDict>;
+
+    fn deref(&self) -> &Self::Target {
+        self.0
+    }
+}
+
+/// Rejects the three-argument form of `pow()`, which has no meaning for floats.
+fn check_no_modulo(modulo: Option<&Bound<PyAny>>) -> PyResult<()> {
+    match modulo {
+        Some(m) if !m.is_none() => Err(PyTypeError::new_err(
+            "pow() 3rd argument not allowed for RedDict",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Shared implementation for binary element-wise operations.
+///
+/// `fill` is the value used when `other` is missing a key present in `self`;
+/// `None` makes those entries null. A null on either side gives a null.
+fn merge<F>(this: &RedDict, other: &RedDict, fill: Option<f64>, f: F) -> RedDict
+where
+    F: Fn(&f64, &f64) -> f64,
+{
+    let mut new = this.clone();
+    merge_into(&mut new, other, fill, f);
+    new
+}
+
+/// `merge` writing into `this`. The values buffer is updated in place when
+/// `this` owns it alone, and copied first otherwise.
+fn merge_into<F>(this: &mut RedDict, other: &RedDict, fill: Option<f64>, f: F)
+where
+    F: Fn(&f64, &f64) -> f64,
+{
+    let len = this.values.len();
+    let nullable = this.validity.is_some() || other.validity.is_some();
+    let alignment = align(&this.index, &other.index);
+
+    // The validity depends on the old null mask, so build it before writing.
+    let validity = match &alignment {
+        Alignment::Identical => {
+            nullable.then(|| Bitmap::from_fn(len, |i| this.is_valid(i) && other.is_valid(i)))
+        }
+        Alignment::Gather(gather) => (nullable || fill.is_none()).then(|| {
+            Bitmap::from_fn(len, |i| {
+                this.is_valid(i) && gather[i].map_or(fill.is_some(), |j| other.is_valid(j))
+            })
+        }),
+    };
+
+    let values = Arc::make_mut(&mut this.values);
+    match alignment {
+        Alignment::Identical => {
+            for (nv, ov) in values.iter_mut().zip(other.values.iter()) {
+                *nv = f(nv, ov);
+            }
+        }
+        Alignment::Gather(gather) => {
+            for (nv, j) in values.iter_mut().zip(gather.iter()) {
+                let rhs = j.map_or(fill.unwrap_or(f64::NAN), |j| other.values[j]);
+                *nv = f(nv, &rhs);
+            }
+        }
+    }
+
+    if let Some(validity) = validity {
+        this.set_validity(validity);
+    }
+}
+
+/// Pairs of values for the keys of `this` (and, with `union`, also the keys
+/// only `other` has), treating RedDicts as sparse vectors: a key missing
+/// from one side, or null there, contributes `0.0` on that side.
+///
+/// Walks the cached alignment directly, so reductions over two RedDicts
+/// never build an intermediate one.
+fn sparse_pairs<'a>(
+    this: &'a RedDict,
+    other: &'a RedDict,
+    union: bool,
+) -> Box<dyn Iterator<Item = (f64, f64)> + 'a> {
+    let left = |i: usize| this.value_at(i).unwrap_or(0.0);
+    let right = |j: usize| other.value_at(j).unwrap_or(0.0);
+    match align(&this.index, &other.index) {
+        Alignment::Identical => Box::new((0..this.values.len()).map(move |i| (left(i), right(i)))),
+        Alignment::Gather(gather) => {
+            let shared =
+                (0..this.values.len()).map(move |i| (left(i), gather[i].map_or(0.0, right)));
+            if !union {
+                return Box::new(shared);
+            }
+            let backward = match align(&other.index, &this.index) {
+                Alignment::Gather(backward) => backward,
+                // Every key of `other` is in `this`, as for a Gather of all
+                // `Some`, so nothing is added.
+                Alignment::Identical => return Box::new(shared),
+            };
+            let right_only = (0..other.values.len())
+                .filter(move |&j| backward[j].is_none())
+                .map(move |j| (0.0, right(j)));
+            Box::new(shared.chain(right_only))
+        }
+    }
+}
+
+/// Whether `this` and `other` hold the same keys, with nulls in the same
+/// places and `same` holding for every pair of values.
+fn entries_match<F>(this: &RedDict, other: &RedDict, same: F) -> bool
+where
+    F: Fn(f64, f64) -> bool,
+{
+    if this.values.len() != other.values.len() {
+        return false;
+    }
+    let matches = |i: usize, j: usize| match (this.value_at(i), other.value_at(j)) {
+        (Some(a), Some(b)) => same(a, b),
+        (a, b) => a.is_none() && b.is_none(),
+    };
+    match align(&this.index, &other.index) {
+        Alignment::Identical => (0..this.values.len()).all(|i| matches(i, i)),
+        Alignment::Gather(gather) => gather
+            .iter()
+            .enumerate()
+            .all(|(i, j)| j.is_some_and(|j| matches(i, j))),
+    }
+}
+
+create_exception!(
+    redbear,
+    KeyMismatchError,
+    PyValueError,
+    "Raised by strict binary operations when the operands' key sets differ."
+);
+
+/// Number of keys listed per side in a `KeyMismatchError` message.
+const MISMATCH_PREVIEW: usize = 10;
+
+/// Raises `KeyMismatchError` when `strict` is set and the key sets differ.
+///
+/// `missing` are keys of `this` absent from `other`, `extra` are keys of
+/// `other` absent from `this`. Both full lists are attached to the exception;
+/// the message shows at most `MISMATCH_PREVIEW` of each.
+fn check_keys(py: Python<'_>, this: &RedDict, other: &RedDict, strict: bool) -> PyResult<()> {
+    if !strict {
+        return Ok(());
+    }
+    let Alignment::Gather(forward) = align(&this.index, &other.index) else {
+        return Ok(());
+    };
+    let missing: Vec<&str> = forward
+        .iter()
+        .zip(this.keys.iter())
+        .filter(|(j, _)| j.is_none())
+        .map(|(_, k)| k.as_str())
+        .collect();
+    let extra: Vec<&str> = match align(&other.index, &this.index) {
+        Alignment::Gather(backward) => backward
+            .iter()
+            .zip(other.keys.iter())
+            .filter(|(i, _)| i.is_none())
+            .map(|(_, k)| k.as_str())
+            .collect(),
+        Alignment::Identical => Vec::new(),
+    };
+    if missing.is_empty() && extra.is_empty() {
+        return Ok(());
+    }
+
+    fn preview(keys: &[&str]) -> String {
+        let shown: Vec<String> = keys
+            .iter()
+            .take(MISMATCH_PREVIEW)
+            .map(|k| format!("{k:?}"))
+            .collect();
+        match keys.len().saturating_sub(MISMATCH_PREVIEW) {
+            0 => format!("[{}]", shown.join(", ")),
+            more => format!("[{}, ... ({more} more)]", shown.join(", ")),
+        }
+    }
+
+    let err = KeyMismatchError::new_err(format!(
+        "key sets differ: {} missing from other {}, {} extra in other {}",
+        missing.len(),
+        preview(&missing),
+        extra.len(),
+        preview(&extra),
+    ));
+    let value = err.value(py);
+    value.setattr("missing", missing)?;
+    value.setattr("extra", extra)?;
+    Err(err)
+}
+
+/// The error for key and value iterables of different lengths.
+fn length_mismatch(keys: &Bound<PyAny>, values: &Bound<PyAny>) -> PyErr {
+    PyValueError::new_err(match (keys.len(), values.len()) {
+        (Ok(keys), Ok(values)) => format!("got {keys} keys and {values} values"),
+        _ => "keys and values differ in length".to_string(),
+    })
+}
+
+/// Which keys the result of a binary operation between RedDicts keeps.
+#[derive(Clone, Copy)]
+enum How {
+    /// Keys of the left operand, in its order.
+    Left,
+    /// Keys of the right operand, in its order.
+    Right,
+    /// Keys present in both operands, in left order.
+    Inner,
+    /// Keys present in either operand: left keys first, then right-only keys.
+    Outer,
+}
+
+impl How {
+    fn parse(how: &str) -> PyResult<Self> {
+        match how {
+            "left" => Ok(How::Left),
+            "right" => Ok(How::Right),
+            "inner" => Ok(How::Inner),
+            "outer" => Ok(How::Outer),
+            _ => Err(PyValueError::new_err(format!(
+                "how must be 'left', 'right', 'inner' or 'outer', got {how:?}"
+            ))),
+        }
+    }
+}
+
+/// Keyword arguments shared by the binary methods, resolved against each
+/// other and the module defaults.
+struct JoinArgs {
+    how: How,
+    fill_left: Option<f64>,
+    fill_right: Option<f64>,
+    strict: bool,
+}
+
+impl JoinArgs {
+    fn new(
+        fill: Option<f64>,
+        how: &str,
+        fill_left: Option<f64>,
+        fill_right: Option<f64>,
+        strict: Option<bool>,
+    ) -> PyResult<Self> {
+        Ok(Self {
+            how: How::parse(how)?,
+            fill_left: fill_left.or(fill),
+            fill_right: fill_right.or(fill),
+            strict: options::resolve_strict(strict),
+        })
+    }
+}
+
+/// Binary element-wise operation with an explicit join mode.
+///
+/// `fill_left` stands in for keys missing from `this` and `fill_right` for
+/// keys missing from `other`. Inner and outer joins produce a new layout,
+/// which is interned so repeated joins of the same pair of layouts keep
+/// hitting the fast path downstream.
+fn join<F>(
+    this: &RedDict,
+    other: &RedDict,
+    how: How,
+    fill_left: Option<f64>,
+    fill_right: Option<f64>,
+    f: F,
+) -> RedDict
+where
+    F: Fn(&f64, &f64) -> f64,
+{
+    let forward = match (how, align(&this.index, &other.index)) {
+        (How::Left, _) | (_, Alignment::Identical) => return merge(this, other, fill_right, f),
+        (How::Right, _) => {
+            return RedDict {
+                frozen: this.frozen,
+                ..merge(other, this, fill_left, |o, t| f(t, o))
+            };
+        }
+        (_, Alignment::Gather(forward)) => forward,
+    };
+
+    let mut keys = Vec::new();
+    let mut pairs = Vec::new();
+    for (i, j) in forward.iter().enumerate() {
+        if j.is_some() || matches!(how, How::Outer) {
+            keys.push(this.keys[i].clone());
+            pairs.push((Some(i), *j));
+        }
+    }
+    if matches!(how, How::Outer) {
+        if let Alignment::Gather(backward) = align(&other.index, &this.index) {
+            for (j, i) in backward.iter().enumerate() {
+                if i.is_none() {
+                    keys.push(other.keys[j].clone());
+                    pairs.push((None, Some(j)));
+                }
+            }
+        }
+    }
+
+    let values = pairs
+        .iter()
+        .map(|(i, j)| {
+            let lhs = i.map_or(fill_left.unwrap_or(f64::NAN), |i| this.values[i]);
+            let rhs = j.map_or(fill_right.unwrap_or(f64::NAN), |j| other.values[j]);
+            f(&lhs, &rhs)
+        })
+        .collect();
+    let validity = Bitmap::from_fn(pairs.len(), |p| {
+        let (i, j) = pairs[p];
+        i.map_or(fill_left.is_some(), |i| this.is_valid(i))
+            && j.map_or(fill_right.is_some(), |j| other.is_valid(j))
+    });
+    let index = keys
+        .iter()
+        .enumerate()
+        .map(|(pos, k)| (k.clone(), pos))
+        .collect();
+    let schema = KeySchema::interned(Arc::new(keys), Arc::new(index));
+
+    RedDict {
+        keys: schema.keys,
+        index: schema.index,
+        values: Arc::new(values),
+        validity: None,
+        frozen: this.frozen,
+    }
+    .with_validity(validity)
+}
+
+/// A Python module implemented in Rust.
+#[pymodule]
+fn redbear(m: &Bound<PyModule>) -> PyResult<()> {
+    m.add_class::<RedDict>()?;
+    m.add_class::<KeySchema>()?;
+    m.add_class::<expr::Expr>()?;
+    m.add("KeyMismatchError", m.py().get_type::<KeyMismatchError>())?;
+    m.add_function(wrap_pyfunction!(options::set_options, m)?)?;
+    m.add_function(wrap_pyfunction!(options::get_options, m)?)?;
+    m.add_function(wrap_pyfunction!(expr::expr, m)?)?;
+    m.add_function(wrap_pyfunction!(format::to_bytes_many, m)?)?;
+    m.add_function(wrap_pyfunction!(format::from_bytes_many, m)?)?;
+    m.add_function(wrap_pyfunction!(format::save_many, m)?)?;
+    m.add_function(wrap_pyfunction!(format::load_many, m)?)?;
+    register_mapping(m.py())?;
+    Ok(())
+}
+
+/// Registers `RedDict` as a virtual `collections.abc.MutableMapping`
+/// subclass (and so also a `Mapping`) so it is accepted wherever a dict is
+/// expected.
+fn register_mapping(py: Python) -> PyResult<()> {
+    py.import("collections.abc")?
+        .getattr("MutableMapping")?
+        .call_method1("register", (py.get_type::<RedDict>(),))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::{types::PyDict, Py, Python};
+    use std::ffi::CStr;
+
+    /// Builds a RedDict from `entries`; `None` values become nulls.
+    pub(crate) fn make_dict<'py, V>(py: Python<'py>, entries: &[(&str, V)]) -> RedDict
+    where
+        V: IntoPyObject<'py> + Copy,
+    {
+        let dict = PyDict::new(py);
+        for (k, v) in entries {
+            dict.set_item(*k, *v).unwrap();
+        }
+        RedDict::py_new(Some(&dict), None, None).unwrap()
+    }
+
+    /// Evaluates a Python expression with `d1` and `d2` bound as RedDicts.
+    fn eval_with<'py>(
+        py: Python<'py>,
+        d1: &RedDict,
+        d2: &RedDict,
+        expr: &CStr,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let locals = PyDict::new(py);
+        locals.set_item("d1", Py::new(py, d1.clone())?)?;
+        locals.set_item("d2", Py::new(py, d2.clone())?)?;
+        py.eval(expr, None, Some(&locals))
+    }
+
+    fn eval_dict(py: Python<'_>, d1: &RedDict, d2: &RedDict, expr: &CStr) -> RedDict {
+        let result = eval_with(py, d1, d2, expr).unwrap();
+        result.cast::<RedDict>().unwrap().borrow().clone()
+    }
+
+    #[test]
+    fn test_new_from_empty_dict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let dict = PyDict::new(py);
+            let rd = RedDict::py_new(Some(&dict), None, None).unwrap();
+            assert_eq!(rd.py_to_dict().len(), 0);
+        });
+    }
+
+    #[test]
+    fn test_new_from_single_entry() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 42.0)]);
+            let map = rd.py_to_dict();
+            assert_eq!(map.get("x"), Some(&Some(42.0)));
+            assert_eq!(map.len(), 1);
+        });
+    }
+
+    #[test]
+    fn test_new_from_multiple_entries() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let map = rd.py_to_dict();
+            assert_eq!(map.get("a"), Some(&Some(1.0)));
+            assert_eq!(map.get("b"), Some(&Some(2.0)));
+            assert_eq!(map.get("c"), Some(&Some(3.0)));
+        });
+    }
+
+    #[test]
+    fn test_add_scalar() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", -2.0)]);
+            let result = rd.add_scalar(3.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(4.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_subtract_scalar() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 5.0), ("b", 3.0)]);
+            let result = rd.subtract_scalar(2.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(3.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_add_scalar_negative() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 10.0)]);
+            let result = rd.add_scalar(-5.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(5.0)));
+        });
+    }
+
+    #[test]
+    fn test_operations_return_new_instance() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0)]);
+            let added = rd.add_scalar(1.0);
+            let subtracted = rd.subtract_scalar(1.0);
+            assert_eq!(rd.py_to_dict().get("a"), Some(&Some(1.0)));
+            assert_eq!(added.py_to_dict().get("a"), Some(&Some(2.0)));
+            assert_eq!(subtracted.py_to_dict().get("a"), Some(&Some(0.0)));
+        });
+    }
+
+    #[test]
+    fn test_add_uses_fill_for_missing_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("b", 10.0), ("c", 100.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(5.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(6.0))); // fill used
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(12.0))); // right value used
+            assert!(!result.py_to_dict().contains_key("c"));
+        });
+    }
+
+    #[test]
+    fn test_subtract_uses_fill_for_missing_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 10.0), ("b", 5.0)]);
+            let right = make_dict(py, &[("b", 2.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_subtract(py_right.bind(py), Some(3.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(7.0))); // fill used
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(3.0))); // right value used
+        });
+    }
+
+    #[test]
+    fn test_multiply_uses_fill_for_missing_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 2.0), ("b", 3.0)]);
+            let right = make_dict(py, &[("b", 10.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_multiply(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(2.0))); // fill used
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(30.0))); // right value used
+        });
+    }
+
+    #[test]
+    fn test_add_default_fill_is_zero() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0)]);
+            let right = make_dict::<f64>(py, &[]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_subtract_default_fill_is_zero() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 5.0)]);
+            let right = make_dict::<f64>(py, &[]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_subtract(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(5.0)));
+        });
+    }
+
+    #[test]
+    fn test_multiply_default_fill_is_one() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 7.0)]);
+            let right = make_dict::<f64>(py, &[]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_multiply(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(7.0)));
+        });
+    }
+
+    #[test]
+    fn test_add_fast_path_identical_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("a", 10.0), ("b", 20.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(11.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(22.0)));
+        });
+    }
+
+    #[test]
+    fn test_subtract_fast_path_identical_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 10.0), ("b", 20.0)]);
+            let right = make_dict(py, &[("a", 3.0), ("b", 5.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_subtract(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(7.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(15.0)));
+        });
+    }
+
+    #[test]
+    fn test_multiply_fast_path_identical_keys() {
+        Python::initialize();
+        Python::attach(|py| {
             let left = make_dict(py, &[("a", 2.0), ("b", 3.0)]);
+            let right = make_dict(py, &[("a", 5.0), ("b", 4.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_multiply(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(10.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(12.0)));
+        });
+    }
+
+    #[test]
+    fn test_does_not_modify_operands() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("b", 10.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let _ = left
+                .py_add(py_right.bind(py), Some(5.0), "left", None, None, None)
+                .unwrap();
+            let _ = left
+                .py_subtract(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            let _ = left
+                .py_multiply(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(left.py_to_dict().get("a"), Some(&Some(1.0)));
+            assert_eq!(left.py_to_dict().get("b"), Some(&Some(2.0)));
+            assert_eq!(right.py_to_dict().get("b"), Some(&Some(10.0)));
+        });
+    }
+
+    #[test]
+    fn test_chained_operations() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 1.0)]);
+            let py_rd = Py::new(py, rd.clone()).unwrap();
+            let result = rd
+                .add_scalar(2.0)
+                .subtract_scalar(1.0)
+                .py_add(py_rd.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("x"), Some(&Some(3.0)));
+        });
+    }
+
+    #[test]
+    fn test_multiply_scalar() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 2.0), ("b", 5.0)]);
+            let result = rd.multiply_scalar(3.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(6.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(15.0)));
+        });
+    }
+
+    #[test]
+    fn test_multiply_scalar_by_zero() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 42.0)]);
+            let result = rd.multiply_scalar(0.0);
+            assert_eq!(result.py_to_dict().get("x"), Some(&Some(0.0)));
+        });
+    }
+
+    #[test]
+    fn test_divide_scalar() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
+            let result = rd.divide_scalar(2.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(5.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(3.0)));
+        });
+    }
+
+    #[test]
+    fn test_divide_scalar_by_fraction() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 1.0)]);
+            let result = rd.divide_scalar(0.5);
+            assert_eq!(result.py_to_dict().get("x"), Some(&Some(2.0)));
+        });
+    }
+
+    #[test]
+    fn test_divide() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
+            let right = make_dict(py, &[("b", 2.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_divide(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(10.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(3.0)));
+        });
+    }
+
+    #[test]
+    fn test_divide_default_fill_is_one() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 7.0)]);
+            let right = make_dict::<f64>(py, &[]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let result = left
+                .py_divide(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(7.0)));
+        });
+    }
+
+    #[test]
+    fn test_sum() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            assert_eq!(rd.py_sum(true, false, "naive").unwrap(), Some(6.0));
+        });
+    }
+
+    #[test]
+    fn test_sum_empty() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict::<f64>(py, &[]);
+            assert_eq!(rd.py_sum(true, false, "naive").unwrap(), Some(0.0));
+        });
+    }
+
+    #[test]
+    fn test_product() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 2.0), ("b", 3.0), ("c", 4.0)]);
+            assert_eq!(rd.py_product(true, false), Some(24.0));
+        });
+    }
+
+    #[test]
+    fn test_product_single_element() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 5.0)]);
+            assert_eq!(rd.py_product(true, false), Some(5.0));
+        });
+    }
+
+    #[test]
+    fn test_reset() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let result = rd.reset(99.0);
+            assert_eq!(result.py_to_dict().get("a"), Some(&Some(99.0)));
+            assert_eq!(result.py_to_dict().get("b"), Some(&Some(99.0)));
+        });
+    }
+
+    #[test]
+    fn test_reset_to_zero() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("x", 42.0)]);
+            let result = rd.reset(0.0);
+            assert_eq!(result.py_to_dict().get("x"), Some(&Some(0.0)));
+        });
+    }
+
+    #[test]
+    fn test_original_unchanged_after_reset() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0)]);
+            let _ = rd.reset(100.0);
+            assert_eq!(rd.py_to_dict().get("a"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_multiply_does_not_modify_operands() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
             let right = make_dict(py, &[("b", 10.0)]);
             let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.multiply(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&2.0)); // fill used
-            assert_eq!(result.to_dict().get("b"), Some(&30.0)); // right value used
+            let _ = left
+                .py_multiply(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(left.py_to_dict().get("a"), Some(&Some(1.0)));
+            assert_eq!(left.py_to_dict().get("b"), Some(&Some(2.0)));
+            assert_eq!(right.py_to_dict().get("b"), Some(&Some(10.0)));
+        });
+    }
+
+    #[test]
+    fn test_divide_does_not_modify_operands() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
+            let right = make_dict(py, &[("b", 2.0)]);
+            let py_right = Py::new(py, right.clone()).unwrap();
+            let _ = left
+                .py_divide(py_right.bind(py), Some(1.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(left.py_to_dict().get("a"), Some(&Some(10.0)));
+            assert_eq!(left.py_to_dict().get("b"), Some(&Some(6.0)));
+        });
+    }
+
+    #[test]
+    fn test_operators_with_reddict() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 2.0), ("b", 4.0)]);
+            let d2 = make_dict(py, &[("b", 2.0), ("c", 100.0)]);
+            let sum = eval_dict(py, &d1, &d2, c"d1 + d2");
+            assert_eq!(sum.py_to_dict().get("a"), Some(&Some(2.0))); // fill 0.0
+            assert_eq!(sum.py_to_dict().get("b"), Some(&Some(6.0)));
+            assert!(!sum.py_to_dict().contains_key("c"));
+            let diff = eval_dict(py, &d1, &d2, c"d1 - d2");
+            assert_eq!(diff.py_to_dict().get("b"), Some(&Some(2.0)));
+            let prod = eval_dict(py, &d1, &d2, c"d1 * d2");
+            assert_eq!(prod.py_to_dict().get("a"), Some(&Some(2.0))); // fill 1.0
+            assert_eq!(prod.py_to_dict().get("b"), Some(&Some(8.0)));
+            let quot = eval_dict(py, &d1, &d2, c"d1 / d2");
+            assert_eq!(quot.py_to_dict().get("a"), Some(&Some(2.0))); // fill 1.0
+            assert_eq!(quot.py_to_dict().get("b"), Some(&Some(2.0)));
+            let pow = eval_dict(py, &d1, &d2, c"d1 ** d2");
+            assert_eq!(pow.py_to_dict().get("a"), Some(&Some(2.0))); // fill 1.0
+            assert_eq!(pow.py_to_dict().get("b"), Some(&Some(16.0)));
+        });
+    }
+
+    #[test]
+    fn test_operators_with_scalars() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 2.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let cases: [(&CStr, f64); 10] = [
+                (c"d1 + 1", 3.0),
+                (c"1 + d1", 3.0),
+                (c"d1 - 1.5", 0.5),
+                (c"1.0 - d1", -1.0),
+                (c"d1 * 3", 6.0),
+                (c"2.0 * d1", 4.0),
+                (c"d1 / 4", 0.5),
+                (c"1 / d1", 0.5),
+                (c"d1 ** 3", 8.0),
+                (c"3 ** d1", 9.0),
+            ];
+            for (expr, expected) in cases {
+                let result = eval_dict(py, &d1, &d2, expr);
+                assert_eq!(
+                    result.py_to_dict().get("a"),
+                    Some(&Some(expected)),
+                    "{expr:?}"
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_unary_operators() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", -2.0), ("b", 3.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let neg = eval_dict(py, &d1, &d2, c"-d1");
+            assert_eq!(neg.py_to_dict().get("a"), Some(&Some(2.0)));
+            assert_eq!(neg.py_to_dict().get("b"), Some(&Some(-3.0)));
+            let abs = eval_dict(py, &d1, &d2, c"abs(d1)");
+            assert_eq!(abs.py_to_dict().get("a"), Some(&Some(2.0)));
+            assert_eq!(abs.py_to_dict().get("b"), Some(&Some(3.0)));
+        });
+    }
+
+    #[test]
+    fn test_inplace_operators() {
+        Python::initialize();
+        Python::attach(|py| {
+            let locals = PyDict::new(py);
+            locals
+                .set_item("d", Py::new(py, make_dict(py, &[("a", 2.0)])).unwrap())
+                .unwrap();
+            locals
+                .set_item("o", Py::new(py, make_dict(py, &[("a", 3.0)])).unwrap())
+                .unwrap();
+            py.run(
+                c"alias = d\nd += o\nd *= 2\nd -= 1\nd /= d\nd **= 2",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let d = locals.get_item("d").unwrap().unwrap();
+            let alias = locals.get_item("alias").unwrap().unwrap();
+            assert!(d.is(&alias));
+            let d = d.cast::<RedDict>().unwrap().borrow().clone();
+            assert_eq!(d.py_to_dict().get("a"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_inplace_operator_does_not_modify_shared_storage() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0)]);
+            let d2 = d1.clone();
+            let locals = PyDict::new(py);
+            locals.set_item("d", Py::new(py, d1).unwrap()).unwrap();
+            py.run(c"d += 10", None, Some(&locals)).unwrap();
+            assert_eq!(d2.py_to_dict().get("a"), Some(&Some(1.0)));
+        });
+    }
+
+    #[test]
+    fn test_operators_reject_unsupported_operands() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let err = eval_with(py, &d1, &d2, c"d1 + 'x'").unwrap_err();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+            let err = eval_with(py, &d1, &d2, c"pow(d1, 2, 3)").unwrap_err();
+            assert!(err.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_len() {
+        Python::initialize();
+        Python::attach(|py| {
+            assert_eq!(make_dict::<f64>(py, &[]).__len__(), 0);
+            assert_eq!(make_dict(py, &[("a", 1.0), ("b", 2.0)]).__len__(), 2);
+        });
+    }
+
+    #[test]
+    fn test_getitem_and_contains() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.5)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let value: f64 = eval_with(py, &d1, &d2, c"d1['a']")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(value, 1.5);
+            let err = eval_with(py, &d1, &d2, c"d1['missing']").unwrap_err();
+            assert!(err.is_instance_of::<PyKeyError>(py));
+            let contains: (bool, bool, bool) =
+                eval_with(py, &d1, &d2, c"('a' in d1, 'b' in d1, 1 in d1)")
+                    .unwrap()
+                    .extract()
+                    .unwrap();
+            assert_eq!(contains, (true, false, false));
+        });
+    }
+
+    #[test]
+    fn test_keys_values_items_are_aligned() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let keys = rd.keys();
+            let values = rd.py_values();
+            for (i, key) in keys.iter().enumerate() {
+                assert_eq!(rd.py_to_dict().get(*key), Some(&values[i]));
+            }
+            let items = rd.py_items();
+            assert_eq!(items.len(), 3);
+            for (key, value) in items {
+                assert_eq!(rd.py_to_dict().get(key), Some(&value));
+            }
+        });
+    }
+
+    #[test]
+    fn test_iter_matches_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let iterated: Vec<String> = eval_with(py, &d1, &d2, c"list(d1)")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(iterated, d1.keys());
+        });
+    }
+
+    #[test]
+    fn test_get_with_default() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let found: (f64, Option<f64>, f64) = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(d1.get('a'), d1.get('b'), d1.get('b', -1.0))",
+            )
+            .unwrap()
+            .extract()
+            .unwrap();
+            assert_eq!(found, (1.0, None, -1.0));
+        });
+    }
+
+    #[test]
+    fn test_registered_as_mapping() {
+        Python::initialize();
+        Python::attach(|py| {
+            register_mapping(py).unwrap();
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let is_mapping: bool = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"isinstance(d1, __import__('collections.abc').abc.MutableMapping)",
+            )
+            .unwrap()
+            .extract()
+            .unwrap();
+            assert!(is_mapping);
+            let plain: IndexMap<String, Option<f64>> = eval_with(py, &d1, &d2, c"dict(d1)")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(plain, d1.py_to_dict());
+        });
+    }
+
+    fn reversed_entries(n: usize) -> Vec<(String, f64)> {
+        (0..n)
+            .rev()
+            .map(|i| (format!("key{i}"), i as f64))
+            .collect()
+    }
+
+    fn make_owned_dict(py: Python<'_>, entries: &[(String, f64)]) -> RedDict {
+        let borrowed: Vec<(&str, f64)> = entries.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+        make_dict(py, &borrowed)
+    }
+
+    #[test]
+    fn test_preserves_insertion_order() {
+        Python::initialize();
+        Python::attach(|py| {
+            let entries = reversed_entries(50);
+            let rd = make_owned_dict(py, &entries);
+            let expected: Vec<&str> = entries.iter().map(|(k, _)| k.as_str()).collect();
+            assert_eq!(rd.keys(), expected);
+            let to_dict: Vec<(String, Option<f64>)> = rd.py_to_dict().into_iter().collect();
+            let expected: Vec<(String, Option<f64>)> =
+                entries.iter().map(|(k, v)| (k.clone(), Some(*v))).collect();
+            assert_eq!(to_dict, expected);
+            let values: Vec<Option<f64>> = entries.iter().map(|(_, v)| Some(*v)).collect();
+            assert_eq!(rd.py_values(), values);
+        });
+    }
+
+    #[test]
+    fn test_python_views_follow_insertion_order() {
+        Python::initialize();
+        Python::attach(|py| {
+            let entries = reversed_entries(20);
+            let d1 = make_owned_dict(py, &entries);
+            let d2 = make_dict::<f64>(py, &[]);
+            let expected: Vec<String> = entries.iter().map(|(k, _)| k.clone()).collect();
+            for expr in [c"list(d1)", c"list(d1.to_dict)", c"list(dict(d1))"] {
+                let keys: Vec<String> = eval_with(py, &d1, &d2, expr).unwrap().extract().unwrap();
+                assert_eq!(keys, expected, "{expr:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_operations_keep_left_order() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("z", 1.0), ("a", 2.0), ("m", 3.0)]);
+            let right = make_dict(py, &[("m", 1.0), ("z", 1.0), ("a", 1.0)]);
+            let py_right = Py::new(py, right).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.keys(), ["z", "a", "m"]);
+            assert_eq!(result.py_values(), [Some(2.0), Some(3.0), Some(4.0)]);
+            assert_eq!(result.multiply_scalar(2.0).keys(), ["z", "a", "m"]);
+        });
+    }
+
+    fn make_schema(keys: &[&str]) -> Py<KeySchema> {
+        Python::attach(|py| {
+            let keys: Vec<String> = keys.iter().map(|k| k.to_string()).collect();
+            let schema = KeySchema::interned(
+                Arc::new(keys.clone()),
+                Arc::new(keys.into_iter().enumerate().map(|(i, k)| (k, i)).collect()),
+            );
+            Py::new(py, schema).unwrap()
+        })
+    }
+
+    #[test]
+    fn test_new_with_schema_reorders_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schema = make_schema(&["a", "b", "c"]);
+            let dict = PyDict::new(py);
+            dict.set_item("c", 3.0).unwrap();
+            dict.set_item("a", 1.0).unwrap();
+            dict.set_item("b", 2.0).unwrap();
+            let rd = RedDict::py_new(Some(&dict), Some(schema.bind(py)), None).unwrap();
+            assert_eq!(rd.keys(), ["a", "b", "c"]);
+            assert_eq!(rd.py_values(), [Some(1.0), Some(2.0), Some(3.0)]);
+            assert!(Arc::ptr_eq(&rd.index, &schema.get().index));
+        });
+    }
+
+    #[test]
+    fn test_new_with_schema_rejects_mismatched_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schema = make_schema(&["a", "b"]);
+            let extra = PyDict::new(py);
+            extra.set_item("a", 1.0).unwrap();
+            extra.set_item("z", 1.0).unwrap();
+            assert!(RedDict::py_new(Some(&extra), Some(schema.bind(py)), None).is_err());
+            let missing = PyDict::new(py);
+            missing.set_item("a", 1.0).unwrap();
+            let err = RedDict::py_new(Some(&missing), Some(schema.bind(py)), None)
+                .err()
+                .unwrap();
+            assert!(err.to_string().contains("\"b\""));
+        });
+    }
+
+    #[test]
+    fn test_new_accepts_kwargs() {
+        Python::initialize();
+        Python::attach(|py| {
+            let kwargs = PyDict::new(py);
+            kwargs.set_item("b", 2.0).unwrap();
+            kwargs.set_item("c", py.None()).unwrap();
+            let rd = RedDict::py_new(None, None, Some(&kwargs)).unwrap();
+            assert_eq!(rd.keys(), ["b", "c"]);
+            assert!(rd.validity.as_ref().is_some_and(|v| !v.get(1)));
+
+            let dict = PyDict::new(py);
+            dict.set_item("a", 1.0).unwrap();
+            dict.set_item("b", 0.0).unwrap();
+            let rd = RedDict::py_new(Some(&dict), None, Some(&kwargs)).unwrap();
+            assert_eq!(rd.keys(), ["a", "b", "c"]);
+            assert_eq!(*rd.values.get(1).unwrap(), 2.0);
+            assert_eq!(dict.len(), 2);
+
+            let schema = make_schema(&["c", "b"]);
+            let rd = RedDict::py_new(None, Some(schema.bind(py)), Some(&kwargs)).unwrap();
+            assert!(Arc::ptr_eq(&rd.index, &schema.get().index));
+            let schema = make_schema(&["c", "b", "a"]);
+            let rd = RedDict::py_new(Some(&dict), Some(schema.bind(py)), Some(&kwargs)).unwrap();
+            assert_eq!(rd.values[1..], [2.0, 1.0]);
+            assert!(rd.validity.as_ref().is_some_and(|v| !v.get(0)));
+            assert!(RedDict::py_new(None, None, None).unwrap().keys.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_from_items() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict::<f64>(py, &[]);
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(d1.from_items([('a', 1.0), ['b', None], ('a', 2)], on_duplicate='sum').to_dict, d1.from_items(iter([('a', 1.0), ('a', 2.0)]), on_duplicate='first').to_dict, d1.from_items({'x': 1.0}.items()).to_dict)",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            let summed: IndexMap<String, Option<f64>> = item(0).extract().unwrap();
+            assert_eq!(
+                summed.into_iter().collect::<Vec<_>>(),
+                [("a".to_string(), Some(3.0)), ("b".to_string(), None)]
+            );
+            let first: HashMap<String, f64> = item(1).extract().unwrap();
+            assert_eq!(first["a"], 1.0);
+            let items: HashMap<String, f64> = item(2).extract().unwrap();
+            assert_eq!(items["x"], 1.0);
+
+            for expr in [
+                c"d1.from_items([('a', 1.0), ('a', 2.0)])",
+                c"d1.from_items([('a', 1.0, 2.0)])",
+                c"d1.from_items([('a', 1.0)], on_duplicate='max')",
+            ] {
+                let err = eval_with(py, &rd, &rd, expr).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+        });
+    }
+
+    #[test]
+    fn test_from_keys_values_and_fromkeys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict::<f64>(py, &[]);
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(d1.from_keys_values(['a', 'b'], (x for x in [1.0, None])).to_dict, d1.fromkeys('aba', 0.5).to_dict, d1.fromkeys(['a']).to_dict)",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            let zipped: IndexMap<String, Option<f64>> = item(0).extract().unwrap();
+            assert_eq!(
+                zipped.into_iter().collect::<Vec<_>>(),
+                [("a".to_string(), Some(1.0)), ("b".to_string(), None)]
+            );
+            let filled: IndexMap<String, f64> = item(1).extract().unwrap();
+            assert_eq!(filled.keys().collect::<Vec<_>>(), ["a", "b"]);
+            assert_eq!(filled["b"], 0.5);
+            let nulls: HashMap<String, Option<f64>> = item(2).extract().unwrap();
+            assert_eq!(nulls["a"], None);
+
+            for expr in [
+                c"d1.from_keys_values(['a', 'b'], [1.0])",
+                c"d1.from_keys_values(['a'], [1.0, 2.0])",
+                c"d1.from_keys_values(['a', 'a'], [1.0, 2.0])",
+            ] {
+                let err = eval_with(py, &rd, &rd, expr).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py));
+            }
+        });
+    }
+
+    #[test]
+    fn test_from_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schema = make_schema(&["x", "y"]);
+            let rd = RedDict::py_from_values(schema.bind(py), vec![Some(1.0), Some(2.0)]).unwrap();
+            assert_eq!(rd.py_to_dict().get("y"), Some(&Some(2.0)));
+            assert!(RedDict::py_from_values(schema.bind(py), vec![Some(1.0)]).is_err());
+        });
+    }
+
+    #[test]
+    fn test_dicts_sharing_schema_share_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let schema = Py::new(py, left.schema()).unwrap();
+            let dict = PyDict::new(py);
+            dict.set_item("b", 20.0).unwrap();
+            dict.set_item("a", 10.0).unwrap();
+            let right = RedDict::py_new(Some(&dict), Some(schema.bind(py)), None).unwrap();
+            assert!(Arc::ptr_eq(&left.index, &right.index));
+            let py_right = Py::new(py, right).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_values(), [Some(11.0), Some(22.0)]);
+        });
+    }
+
+    #[test]
+    fn test_schema_getter_is_interned() {
+        Python::initialize();
+        Python::attach(|py| {
+            let earlier = make_schema(&["schema-getter-a", "schema-getter-b"]);
+            let rd = make_dict(py, &[("schema-getter-a", 1.0), ("schema-getter-b", 2.0)]);
+            assert!(!Arc::ptr_eq(&rd.index, &earlier.get().index));
+
+            let rd = Bound::new(py, rd).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("d", &rd).unwrap();
+            let built = py
+                .eval(
+                    c"type(d)({'schema-getter-b': 3.0, 'schema-getter-a': 4.0}, schema=d.schema)",
+                    None,
+                    Some(&locals),
+                )
+                .unwrap();
+            let built = built.cast::<RedDict>().unwrap().borrow();
+            assert!(Arc::ptr_eq(&rd.borrow().index, &earlier.get().index));
+            assert!(Arc::ptr_eq(&built.index, &rd.borrow().index));
+            assert!(Arc::ptr_eq(&built.keys, &rd.borrow().keys));
+        });
+    }
+
+    #[test]
+    fn test_how_right_uses_right_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("c", 30.0), ("b", 20.0)]);
+            let result = eval_dict(
+                py,
+                &left,
+                &right,
+                c"d1.subtract(d2, how='right', fill_left=100.0)",
+            );
+            assert_eq!(result.keys(), ["c", "b"]);
+            assert_eq!(result.py_values(), [Some(70.0), Some(-18.0)]);
+        });
+    }
+
+    #[test]
+    fn test_how_inner_keeps_shared_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let right = make_dict(py, &[("c", 30.0), ("b", 20.0), ("d", 40.0)]);
+            let result = eval_dict(py, &left, &right, c"d1.multiply(d2, how='inner')");
+            assert_eq!(result.keys(), ["b", "c"]);
+            assert_eq!(result.py_values(), [Some(40.0), Some(90.0)]);
+        });
+    }
+
+    #[test]
+    fn test_how_outer_keeps_all_keys_with_separate_fills() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("c", 30.0), ("b", 20.0)]);
+            let result = eval_dict(
+                py,
+                &left,
+                &right,
+                c"d1.subtract(d2, how='outer', fill_left=100.0, fill_right=-1.0)",
+            );
+            assert_eq!(result.keys(), ["a", "b", "c"]);
+            assert_eq!(result.py_values(), [Some(2.0), Some(-18.0), Some(70.0)]);
+        });
+    }
+
+    #[test]
+    fn test_how_outer_falls_back_to_fill() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0)]);
+            let right = make_dict(py, &[("c", 3.0)]);
+            let result = eval_dict(py, &left, &right, c"d1.add(d2, fill=10.0, how='outer')");
+            assert_eq!(result.py_values(), [Some(11.0), Some(13.0)]);
+        });
+    }
+
+    #[test]
+    fn test_repeated_outer_joins_share_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0)]);
+            let right = make_dict(py, &[("b", 2.0)]);
+            let first = eval_dict(py, &left, &right, c"d1.add(d2, how='outer')");
+            let second = eval_dict(py, &left, &right, c"d1.divide(d2, how='outer')");
+            assert!(Arc::ptr_eq(&first.index, &second.index));
+        });
+    }
+
+    #[test]
+    fn test_invalid_how_is_rejected() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0)]);
+            let result = eval_with(py, &left, &left, c"d1.add(d2, how='sideways')");
+            assert!(result.is_err());
+        });
+    }
+
+    #[test]
+    fn test_strict_allows_same_keys_in_different_order() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("b", 20.0), ("a", 10.0)]);
+            let result = eval_dict(py, &left, &right, c"d1.add(d2, strict=True)");
+            assert_eq!(result.py_values(), [Some(11.0), Some(22.0)]);
+        });
+    }
+
+    #[test]
+    fn test_strict_raises_key_mismatch_error() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("b", 20.0), ("c", 30.0)]);
+            let err = eval_with(
+                py,
+                &left,
+                &right,
+                c"d1.multiply(d2, how='outer', strict=True)",
+            )
+            .unwrap_err();
+            assert!(err.is_instance_of::<KeyMismatchError>(py));
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let missing: Vec<String> = err.value(py).getattr("missing").unwrap().extract().unwrap();
+            let extra: Vec<String> = err.value(py).getattr("extra").unwrap().extract().unwrap();
+            assert_eq!(missing, ["a"]);
+            assert_eq!(extra, ["c"]);
+        });
+    }
+
+    #[test]
+    fn test_strict_false_overrides_default() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0)]);
+            let right = make_dict::<f64>(py, &[]);
+            let result = eval_dict(py, &left, &right, c"d1.subtract(d2, strict=False)");
+            assert_eq!(result.py_values(), [Some(1.0)]);
+        });
+    }
+
+    #[test]
+    fn test_set_options_strict_is_the_default() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = Bound::new(py, make_dict(py, &[("a", 1.0), ("b", 2.0)])).unwrap();
+            let right = Bound::new(py, make_dict(py, &[("b", 20.0)])).unwrap();
+            let operand = || Operand::Dict(right.clone());
+
+            // The option is process-wide, so run no Python while it is set:
+            // other tests then cannot take the GIL until it is restored.
+            let previous = options::strict();
+            options::set_options(Some(true), None);
+            let shown = options::get_options(py)
+                .unwrap()
+                .get_item("strict")
+                .unwrap();
+            let d1 = left.borrow().clone();
+            let errors = [
+                d1.__add__(operand()).err(),
+                d1.__mul__(operand()).err(),
+                d1.py_add(&right, Some(0.0), "left", None, None, None).err(),
+                d1.py_divide(&right, Some(1.0), "left", None, None, None)
+                    .err(),
+                RedDict::__iadd__(Unfrozen(&left), operand()).err(),
+                RedDict::iadd(&left, &right, None, None).err(),
+            ];
+            let relaxed = d1.py_add(&right, Some(0.0), "left", None, None, Some(false));
+            options::set_options(Some(previous), None);
+
+            assert!(shown.unwrap().extract::<bool>().unwrap());
+            for (i, err) in errors.into_iter().enumerate() {
+                let err = err.unwrap_or_else(|| panic!("operation {i} accepted mismatched keys"));
+                assert!(err.is_instance_of::<KeyMismatchError>(py), "operation {i}");
+            }
+            assert_eq!(*relaxed.unwrap().values, [1.0, 22.0]);
+            assert_eq!(*left.borrow().values, [1.0, 2.0]);
+        });
+    }
+
+    #[test]
+    fn test_key_mismatch_message_is_truncated() {
+        Python::initialize();
+        Python::attach(|py| {
+            let entries = reversed_entries(100);
+            let left = make_owned_dict(py, &entries);
+            let right = make_dict::<f64>(py, &[]);
+            let err = check_keys(py, &left, &right, true).err().unwrap();
+            let message = err.value(py).to_string();
+            assert!(message.contains("100 missing"));
+            assert!(message.contains("(90 more)"));
+            let missing: Vec<String> = err.value(py).getattr("missing").unwrap().extract().unwrap();
+            assert_eq!(missing.len(), 100);
+        });
+    }
+
+    #[test]
+    fn test_none_values_become_nulls() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(1.0)), ("b", None), ("c", Some(f64::NAN))]);
+            assert_eq!(rd.null_count(), 1);
+            assert_eq!(rd.py_to_dict().get("b"), Some(&None));
+            assert!(rd.py_to_dict()["c"].unwrap().is_nan()); // NaN is a value, not null
+            let d2 = make_dict::<f64>(py, &[]);
+            let item: Option<f64> = eval_with(py, &rd, &d2, c"d1['b']")
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert_eq!(item, None);
+        });
+    }
+
+    #[test]
+    fn test_dict_without_nulls_has_no_bitmap() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(1.0))]);
+            assert!(rd.validity.is_none());
+            assert!(make_dict(py, &[("a", 1.0)]).validity.is_none());
+        });
+    }
+
+    #[test]
+    fn test_binary_ops_propagate_nulls() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", Some(1.0)), ("b", None), ("c", Some(3.0))]);
+            let right = make_dict(py, &[("c", None), ("a", Some(10.0)), ("b", Some(20.0))]);
+            let py_right = Py::new(py, right).unwrap();
+            let result = left
+                .py_add(py_right.bind(py), Some(0.0), "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_values(), [Some(11.0), None, None]);
+        });
+    }
+
+    #[test]
+    fn test_fill_none_produces_nulls_for_missing_keys() {
+        Python::initialize();
+        Python::attach(|py| {
+            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let right = make_dict(py, &[("b", 20.0), ("c", 30.0)]);
+            let py_right = Py::new(py, right).unwrap();
+            let result = left
+                .py_multiply(py_right.bind(py), None, "left", None, None, None)
+                .unwrap();
+            assert_eq!(result.py_values(), [None, Some(40.0)]);
+            let outer = left
+                .py_subtract(py_right.bind(py), None, "outer", None, Some(0.0), None)
+                .unwrap();
+            assert_eq!(outer.py_values(), [Some(1.0), Some(-18.0), None]);
+        });
+    }
+
+    #[test]
+    fn test_scalar_ops_keep_nulls() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(2.0)), ("b", None)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let result = eval_dict(py, &d1, &d2, c"(d1 + 1) ** 0");
+            assert_eq!(result.py_values(), [Some(1.0), None]);
+            assert!(result.values[1].is_nan());
+        });
+    }
+
+    #[test]
+    fn test_sum_and_product_skipna() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(2.0)), ("b", None), ("c", Some(3.0))]);
+            let empty = make_dict::<f64>(py, &[]);
+            let reductions = eval_with(
+                py,
+                &rd,
+                &empty,
+                c"(d1.sum(), d1.sum(skipna=False), d1.product(), d1.product(skipna=False))",
+            )
+            .unwrap();
+            let reductions: [Option<f64>; 4] = reductions.extract().unwrap();
+            assert_eq!(reductions, [Some(5.0), None, Some(6.0), None]);
+        });
+    }
+
+    #[test]
+    fn test_fillna_and_reset_clear_nulls() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", Some(2.0)), ("b", None)]);
+            let filled = rd.fillna(-1.0);
+            assert_eq!(filled.py_values(), [Some(2.0), Some(-1.0)]);
+            assert!(filled.validity.is_none());
+            assert_eq!(rd.reset(0.0).null_count(), 0);
+            assert_eq!(rd.null_count(), 1);
+        });
+    }
+
+    #[test]
+    fn test_maximum_and_minimum_keep_unmatched_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 5.0)]);
+            let d2 = make_dict(py, &[("a", 3.0)]);
+            let max = eval_dict(py, &d1, &d2, c"d1.maximum(d2)");
+            assert_eq!(*max.values, [3.0, 5.0]);
+            let min = eval_dict(py, &d1, &d2, c"d1.minimum(d2)");
+            assert_eq!(*min.values, [1.0, 5.0]);
+            assert_eq!(*d1.maximum_scalar(2.0).values, [2.0, 5.0]);
+            assert_eq!(*d1.minimum_scalar(2.0).values, [1.0, 2.0]);
+
+            let d3 = make_dict(py, &[("a", f64::NAN), ("b", 4.0)]);
+            let max = eval_dict(py, &d1, &d3, c"d1.maximum(d2)");
+            assert!(max.values[0].is_nan());
+            assert_eq!(max.values[1], 5.0);
+            let min = eval_dict(py, &d3, &d1, c"d1.minimum(d2)");
+            assert!(min.values[0].is_nan());
+            assert_eq!(min.values[1], 4.0);
+            assert!(d3.maximum_scalar(2.0).values[0].is_nan());
+            assert!(d1
+                .minimum_scalar(f64::NAN)
+                .values
+                .iter()
+                .all(|v| v.is_nan()));
+        });
+    }
+
+    #[test]
+    fn test_modulo_and_floor_divide_follow_python() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 7.0), ("b", -7.0), ("c", 1.0)]);
+            let d2 = make_dict(py, &[("a", 3.0), ("b", 3.0)]);
+            let rem = eval_dict(py, &d1, &d2, c"d1.modulo(d2)");
+            assert_eq!(rem.py_values(), [Some(1.0), Some(2.0), None]);
+            let quot = eval_dict(py, &d1, &d2, c"d1.floor_divide(d2, fill=1.0)");
+            assert_eq!(quot.py_values(), [Some(2.0), Some(-3.0), Some(1.0)]);
+            let ops = eval_dict(py, &d1, &d2, c"(d1 % -2.0) + (d1 // 2.0)");
+            assert_eq!(*ops.values, [2.0, -5.0, -1.0]);
+        });
+    }
+
+    #[test]
+    fn test_modulo_operator_matches_python_floats() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 5.5), ("b", -0.3)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"[*(d1 % 0.1).values(), *(2.0 // d1).values()] \
+                  == [5.5 % 0.1, -0.3 % 0.1, 2.0 // 5.5, 2.0 // -0.3]",
+            )
+            .unwrap();
+            assert!(result.extract::<bool>().unwrap());
         });
     }
 
     #[test]
-    fn test_add_default_fill_is_zero() {
+    fn test_power_atan2_hypot() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 1.0)]);
-            let right = make_dict(py, &[]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.add(py_right.bind(py), 0.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&1.0));
+            let d1 = make_dict(py, &[("a", 3.0), ("b", -2.0)]);
+            let d2 = make_dict(py, &[("a", 4.0)]);
+            let pow = eval_dict(py, &d1, &d2, c"d1.power(d2)");
+            assert_eq!(*pow.values, [81.0, -2.0]);
+            let hyp = eval_dict(py, &d1, &d2, c"d1.hypot(d2)");
+            assert_eq!(*hyp.values, [5.0, 2.0]);
+            let angle = eval_dict(py, &d1, &d2, c"d1.atan2(d2, how='inner')");
+            assert_eq!(*angle.values, [3.0_f64.atan2(4.0)]);
+            assert_eq!(
+                d1.atan2_scalar(-1.0).py_values(),
+                [Some(3.0_f64.atan2(-1.0)), Some((-2.0_f64).atan2(-1.0))]
+            );
         });
     }
 
     #[test]
-    fn test_subtract_default_fill_is_zero() {
+    fn test_inplace_modulo_and_floor_divide() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 5.0)]);
-            let right = make_dict(py, &[]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.subtract(py_right.bind(py), 0.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&5.0));
+            let d1 = make_dict(py, &[("a", 7.0)]);
+            let d2 = make_dict(py, &[("a", 4.0)]);
+            let result = eval_with(py, &d1, &d2, c"[d1.__imod__(d2), d1][1] // 2.0").unwrap();
+            let result = result.cast::<RedDict>().unwrap().borrow();
+            assert_eq!(*result.values, [1.0]);
         });
     }
 
     #[test]
-    fn test_multiply_default_fill_is_one() {
+    fn test_unary_methods_share_index() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 7.0)]);
-            let right = make_dict(py, &[]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.multiply(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&7.0));
+            let rd = make_dict(py, &[("a", 4.0), ("b", 0.25)]);
+            let root = rd.sqrt();
+            assert_eq!(*root.values, [2.0, 0.5]);
+            assert!(Arc::ptr_eq(&root.index, &rd.index));
+            assert!(Arc::ptr_eq(&root.keys, &rd.keys));
+            assert!(!Arc::ptr_eq(&root.values, &rd.values));
+            assert_eq!(*rd.values, [4.0, 0.25]);
         });
     }
 
     #[test]
-    fn test_add_fast_path_identical_keys() {
+    fn test_unary_math_values() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
-            let right = make_dict(py, &[("a", 10.0), ("b", 20.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.add(py_right.bind(py), 0.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&11.0));
-            assert_eq!(result.to_dict().get("b"), Some(&22.0));
+            let rd = make_dict(py, &[("a", -2.5), ("b", 1.0)]);
+            assert_eq!(*rd.abs().values, [2.5, 1.0]);
+            assert_eq!(*rd.neg().values, [2.5, -1.0]);
+            assert_eq!(*rd.floor().values, [-3.0, 1.0]);
+            assert_eq!(*rd.ceil().values, [-2.0, 1.0]);
+            assert_eq!(*rd.round(0).values, [-2.0, 1.0]);
+            assert_eq!(*rd.sign().values, [-1.0, 1.0]);
+            assert_eq!(*rd.reciprocal().values, [-0.4, 1.0]);
+            assert_eq!(*rd.exp().log().values, [-2.5, 1.0]);
+            assert_eq!(rd.log10().values[1], 0.0);
+            assert!(rd.log1p().values[0].is_nan());
+            assert_eq!(*rd.atan().values, [(-2.5_f64).atan(), 1.0_f64.atan()]);
+            assert_eq!(*rd.cos().values, [(-2.5_f64).cos(), 1.0_f64.cos()]);
         });
     }
 
     #[test]
-    fn test_subtract_fast_path_identical_keys() {
+    fn test_clip_bounds() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 10.0), ("b", 20.0)]);
-            let right = make_dict(py, &[("a", 3.0), ("b", 5.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.subtract(py_right.bind(py), 0.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&7.0));
-            assert_eq!(result.to_dict().get("b"), Some(&15.0));
+            let rd = make_dict(py, &[("a", -3.0), ("b", 0.5), ("c", 7.0)]);
+            assert_eq!(
+                *rd.clip(Some(0.0), Some(1.0)).unwrap().values,
+                [0.0, 0.5, 1.0]
+            );
+            assert_eq!(*rd.clip(None, Some(0.0)).unwrap().values, [-3.0, 0.0, 0.0]);
+            assert!(rd.clip(None, None).is_err());
+            assert!(rd.clip(Some(1.0), Some(0.0)).is_err());
         });
     }
 
     #[test]
-    fn test_multiply_fast_path_identical_keys() {
+    fn test_unary_methods_keep_nulls() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 2.0), ("b", 3.0)]);
-            let right = make_dict(py, &[("a", 5.0), ("b", 4.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.multiply(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&10.0));
-            assert_eq!(result.to_dict().get("b"), Some(&12.0));
+            let rd = make_dict(py, &[("a", Some(4.0)), ("b", None)]);
+            let result = rd.sqrt().clip(Some(0.0), None).unwrap();
+            assert_eq!(result.py_values(), [Some(2.0), None]);
+            assert!(Arc::ptr_eq(
+                result.validity.as_ref().unwrap(),
+                rd.validity.as_ref().unwrap()
+            ));
         });
     }
 
     #[test]
-    fn test_does_not_modify_operands() {
+    fn test_mean_min_max_and_args() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
-            let right = make_dict(py, &[("b", 10.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let _ = left.add(py_right.bind(py), 5.0).unwrap();
-            let _ = left.subtract(py_right.bind(py), 0.0).unwrap();
-            let _ = left.multiply(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(left.to_dict().get("a"), Some(&1.0));
-            assert_eq!(left.to_dict().get("b"), Some(&2.0));
-            assert_eq!(right.to_dict().get("b"), Some(&10.0));
+            let rd = make_dict(py, &[("a", 3.0), ("b", 1.0), ("c", 5.0), ("d", 1.0)]);
+            let mean = eval_with(py, &rd, &rd, c"d1.mean()").unwrap();
+            assert_eq!(mean.extract::<f64>().unwrap(), 2.5);
+            assert_eq!(rd.min(true, false), Some(1.0));
+            assert_eq!(rd.max(true, false), Some(5.0));
+            assert_eq!(rd.argmin(true, false).unwrap(), Some("b"));
+            assert_eq!(rd.argmax(true, false).unwrap(), Some("c"));
         });
     }
 
     #[test]
-    fn test_chained_operations() {
+    fn test_reductions_skip_nulls_and_nan() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 1.0)]);
-            let py_rd = Py::new(py, rd.clone()).unwrap();
-            let result = rd
-                .add_scalar(2.0)
-                .subtract_scalar(1.0)
-                .add(py_rd.bind(py), 0.0)
-                .unwrap();
-            assert_eq!(result.to_dict().get("x"), Some(&3.0));
+            let rd = make_dict(
+                py,
+                &[
+                    ("a", Some(2.0)),
+                    ("b", None),
+                    ("c", Some(f64::NAN)),
+                    ("d", Some(4.0)),
+                ],
+            );
+            let means = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(d1.mean(), d1.mean(skipnan=True), d1.mean(skipna=False, skipnan=True))",
+            )
+            .unwrap();
+            let (mean, skipnan, keepna): (f64, Option<f64>, Option<f64>) = means.extract().unwrap();
+            assert!(mean.is_nan());
+            assert_eq!(skipnan, Some(3.0));
+            assert_eq!(keepna, None);
+            assert!(rd.max(true, false).unwrap().is_nan());
+            assert_eq!(rd.max(true, true), Some(4.0));
+            assert_eq!(rd.argmin(true, false).unwrap(), Some("c"));
+            assert_eq!(rd.argmin(true, true).unwrap(), Some("a"));
+            assert_eq!(rd.argmin(false, true).unwrap(), None);
+            assert_eq!(rd.count(false), 3);
+            assert_eq!(rd.count(true), 2);
+            assert_eq!(rd.median(true, true), Some(3.0));
         });
     }
 
     #[test]
-    fn test_multiply_scalar() {
+    fn test_empty_reductions() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 2.0), ("b", 5.0)]);
-            let result = rd.multiply_scalar(3.0);
-            assert_eq!(result.to_dict().get("a"), Some(&6.0));
-            assert_eq!(result.to_dict().get("b"), Some(&15.0));
+            let rd = make_dict(py, &[("a", None::<f64>)]);
+            assert!(rd.min(true, false).unwrap().is_nan());
+            let mean = eval_with(py, &rd, &rd, c"d1.mean()").unwrap();
+            assert!(mean.extract::<f64>().unwrap().is_nan());
+            assert!(rd.argmax(true, false).is_err());
+            assert_eq!(rd.any(true, false), Some(false));
+            assert_eq!(rd.all(true, false), Some(true));
+            assert_eq!(rd.count(false), 0);
         });
     }
 
     #[test]
-    fn test_multiply_scalar_by_zero() {
+    fn test_spread_and_quantiles() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 42.0)]);
-            let result = rd.multiply_scalar(0.0);
-            assert_eq!(result.to_dict().get("x"), Some(&0.0));
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0), ("d", 4.0)]);
+            assert_eq!(rd.var(0, true, false), Some(1.25));
+            assert_eq!(rd.std(0, true, false), Some(1.25_f64.sqrt()));
+            assert_eq!(rd.var(1, true, false), Some(5.0 / 3.0));
+            assert_eq!(rd.median(true, false), Some(2.5));
+            assert_eq!(rd.quantile(0.75, true, false).unwrap(), Some(3.25));
+            assert!(rd.quantile(1.5, true, false).is_err());
         });
     }
 
     #[test]
-    fn test_divide_scalar() {
+    fn test_any_all_and_norm() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
-            let result = rd.divide_scalar(2.0);
-            assert_eq!(result.to_dict().get("a"), Some(&5.0));
-            assert_eq!(result.to_dict().get("b"), Some(&3.0));
+            let rd = make_dict(py, &[("a", 0.0), ("b", 3.0), ("c", -4.0)]);
+            assert_eq!(rd.any(true, false), Some(true));
+            assert_eq!(rd.all(true, false), Some(false));
+            assert_eq!(rd.norm(2.0, true, false).unwrap(), Some(5.0));
+            assert_eq!(rd.norm(f64::INFINITY, true, false).unwrap(), Some(4.0));
+            assert!(rd.norm(-1.0, true, false).is_err());
         });
     }
 
     #[test]
-    fn test_divide_scalar_by_fraction() {
+    fn test_sum_methods_match_fsum() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 1.0)]);
-            let result = rd.divide_scalar(0.5);
-            assert_eq!(result.to_dict().get("x"), Some(&2.0));
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 1e100), ("c", 1.0), ("d", -1e100)]);
+            let d2 = make_dict::<f64>(py, &[]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"__import__('math').fsum(d1.values()) == d1.sum(method='kahan') != d1.sum()",
+            )
+            .unwrap();
+            assert!(result.extract::<bool>().unwrap());
+
+            let keys = ["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"];
+            let tenths =
+                RedDict::py_from_values(make_schema(&keys).bind(py), vec![Some(0.1); 10]).unwrap();
+            assert_eq!(tenths.py_sum(true, false, "kahan").unwrap(), Some(1.0));
+            assert_ne!(tenths.py_sum(true, false, "naive").unwrap(), Some(1.0));
+            assert_eq!(tenths.mean(true, false, "kahan").unwrap(), Some(0.1));
         });
     }
 
     #[test]
-    fn test_divide() {
+    fn test_invalid_sum_method_is_rejected() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
-            let right = make_dict(py, &[("b", 2.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.divide(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&10.0));
-            assert_eq!(result.to_dict().get("b"), Some(&3.0));
+            let rd = make_dict(py, &[("a", 1.0)]);
+            assert!(rd.py_sum(true, false, "fast").is_err());
+            assert_eq!(rd.py_sum(true, false, "pairwise").unwrap(), Some(1.0));
         });
     }
 
     #[test]
-    fn test_divide_default_fill_is_one() {
+    fn test_dot_uses_sparse_semantics() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 7.0)]);
-            let right = make_dict(py, &[]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let result = left.divide(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(result.to_dict().get("a"), Some(&7.0));
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let d2 = make_dict(py, &[("c", 1.0), ("a", 2.0), ("z", 5.0)]);
+            let result = eval_with(py, &d1, &d2, c"(d1.dot(d2), d2.dot(d1), d1.dot(d1))").unwrap();
+            assert_eq!(
+                result.extract::<(f64, f64, f64)>().unwrap(),
+                (5.0, 5.0, 14.0)
+            );
+            let kahan = eval_with(py, &d1, &d2, c"d1.dot(d2, method='kahan')").unwrap();
+            assert_eq!(kahan.extract::<f64>().unwrap(), 5.0);
         });
     }
 
     #[test]
-    fn test_sum() {
+    fn test_distances_cover_union_of_keys() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
-            assert_eq!(rd.sum(), 6.0);
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 4.0)]);
+            let d2 = make_dict(py, &[("a", 4.0), ("c", 4.0)]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(d1.euclidean(d2), d2.euclidean(d1), d1.manhattan(d2))",
+            )
+            .unwrap();
+            assert_eq!(
+                result.extract::<(f64, f64, f64)>().unwrap(),
+                (41.0_f64.sqrt(), 41.0_f64.sqrt(), 11.0)
+            );
+            let same = eval_with(py, &d1, &d2, c"(d1.euclidean(d1), d1.manhattan(d1))").unwrap();
+            assert_eq!(same.extract::<(f64, f64)>().unwrap(), (0.0, 0.0));
         });
     }
 
     #[test]
-    fn test_sum_empty() {
+    fn test_cosine_and_jaccard() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[]);
-            assert_eq!(rd.sum(), 0.0);
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 1.0), ("c", 1.0)]);
+            let d2 = make_dict(py, &[("b", 1.0), ("c", 1.0), ("d", 1.0)]);
+            let result = eval_with(py, &d1, &d2, c"(d1.cosine(d2), d1.jaccard(d2))").unwrap();
+            let (cosine, jaccard) = result.extract::<(f64, f64)>().unwrap();
+            assert!((cosine - 2.0 / 3.0).abs() < 1e-12);
+            assert_eq!(jaccard, 0.5);
+            let own = eval_with(py, &d1, &d2, c"(d1.cosine(d1), d1.jaccard(d1))").unwrap();
+            let (cosine, jaccard) = own.extract::<(f64, f64)>().unwrap();
+            assert!((cosine - 1.0).abs() < 1e-12);
+            assert_eq!(jaccard, 1.0);
         });
     }
 
     #[test]
-    fn test_product() {
+    fn test_metrics_treat_nulls_as_zero() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("a", 2.0), ("b", 3.0), ("c", 4.0)]);
-            assert_eq!(rd.product(), 24.0);
+            let d1 = make_dict(py, &[("a", Some(3.0)), ("b", None)]);
+            let d2 = make_dict(py, &[("a", 1.0), ("b", 10.0)]);
+            let result = eval_with(py, &d1, &d2, c"(d1.dot(d2), d1.manhattan(d2))").unwrap();
+            assert_eq!(result.extract::<(f64, f64)>().unwrap(), (3.0, 12.0));
+            let empty = make_dict::<f64>(py, &[]);
+            let nan = eval_with(py, &d1, &empty, c"d1.cosine(d2)").unwrap();
+            assert!(nan.extract::<f64>().unwrap().is_nan());
         });
     }
 
     #[test]
-    fn test_product_single_element() {
+    fn test_iadd_reuses_unique_buffer() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 5.0)]);
-            assert_eq!(rd.product(), 5.0);
+            let d1 = Bound::new(py, make_dict(py, &[("a", 1.0), ("b", 2.0)])).unwrap();
+            let d2 = Bound::new(py, make_dict(py, &[("b", 10.0), ("a", 20.0)])).unwrap();
+            let before = Arc::as_ptr(&d1.borrow().values);
+            RedDict::iadd(&d1, &d2, Some(0.0), None).unwrap();
+            d1.borrow_mut().imultiply_scalar(2.0).unwrap();
+            assert_eq!(*d1.borrow().values, [42.0, 24.0]);
+            assert_eq!(Arc::as_ptr(&d1.borrow().values), before);
+            assert_eq!(*d2.borrow().values, [10.0, 20.0]);
         });
     }
 
     #[test]
-    fn test_reset() {
+    fn test_inplace_copies_shared_buffer() {
         Python::initialize();
         Python::attach(|py| {
             let rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
-            let result = rd.reset(99.0);
-            assert_eq!(result.to_dict().get("a"), Some(&99.0));
-            assert_eq!(result.to_dict().get("b"), Some(&99.0));
+            let alias = rd.fillna(0.0);
+            assert!(Arc::ptr_eq(&alias.values, &rd.values));
+            let d1 = Bound::new(py, rd).unwrap();
+            d1.borrow_mut().iadd_scalar(1.0).unwrap();
+            assert_eq!(*d1.borrow().values, [2.0, 3.0]);
+            assert_eq!(*alias.values, [1.0, 2.0]);
         });
     }
 
     #[test]
-    fn test_reset_to_zero() {
+    fn test_inplace_with_itself_and_missing_keys() {
         Python::initialize();
         Python::attach(|py| {
-            let rd = make_dict(py, &[("x", 42.0)]);
-            let result = rd.reset(0.0);
-            assert_eq!(result.to_dict().get("x"), Some(&0.0));
+            let d1 = Bound::new(py, make_dict(py, &[("a", 3.0), ("b", 4.0)])).unwrap();
+            RedDict::imultiply(&d1, &d1, Some(1.0), None).unwrap();
+            assert_eq!(*d1.borrow().values, [9.0, 16.0]);
+
+            let d2 = Bound::new(py, make_dict(py, &[("a", 3.0)])).unwrap();
+            RedDict::idivide(&d1, &d2, None, None).unwrap();
+            assert_eq!(d1.borrow().py_values(), [Some(3.0), None]);
+            assert!(RedDict::isubtract(&d1, &d2, Some(0.0), Some(true)).is_err());
+            assert_eq!(d1.borrow().py_values(), [Some(3.0), None]);
         });
     }
 
     #[test]
-    fn test_original_unchanged_after_reset() {
+    fn test_augmented_assignment_updates_in_place() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = Py::new(py, make_dict(py, &[("a", 7.0), ("b", 2.0)])).unwrap();
+            let locals = PyDict::new(py);
+            locals.set_item("d", &d1).unwrap();
+            py.run(
+                c"d += d\nd -= 1\nd //= 2.0\nd **= 2\nsame = d",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            assert!(locals.get_item("same").unwrap().unwrap().is(&d1));
+            assert_eq!(*d1.borrow(py).values, [36.0, 1.0]);
+        });
+    }
+
+    /// Checks that every key's index entry points at its own position.
+    fn assert_dense(rd: &RedDict) {
+        assert_eq!(rd.index.len(), rd.keys.len());
+        assert_eq!(rd.values.len(), rd.keys.len());
+        for (pos, key) in rd.keys.iter().enumerate() {
+            assert_eq!(rd.index[key], pos, "{key}");
+        }
+    }
+
+    #[test]
+    fn test_setitem_overwrites_and_appends() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let snapshot = rd.clone();
+            rd.__setitem__("a".into(), Some(10.0)).unwrap();
+            rd.__setitem__("c".into(), Some(3.0)).unwrap();
+            rd.__setitem__("b".into(), None).unwrap();
+            assert_eq!(rd.keys(), ["a", "b", "c"]);
+            assert_eq!(rd.py_values(), [Some(10.0), None, Some(3.0)]);
+            assert_dense(&rd);
+            rd.__setitem__("b".into(), Some(2.0)).unwrap();
+            assert!(rd.validity.is_none());
+            assert_eq!(snapshot.py_to_dict().len(), 2);
+            assert_eq!(*snapshot.values, [1.0, 2.0]);
+        });
+    }
+
+    #[test]
+    fn test_delitem_compacts_values_and_index() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut rd = make_dict(
+                py,
+                &[
+                    ("a", Some(1.0)),
+                    ("b", None),
+                    ("c", Some(3.0)),
+                    ("d", Some(4.0)),
+                ],
+            );
+            let key = PyString::new(py, "b");
+            rd.__delitem__(&key).unwrap();
+            assert_eq!(rd.keys(), ["a", "c", "d"]);
+            assert_eq!(rd.py_values(), [Some(1.0), Some(3.0), Some(4.0)]);
+            assert!(rd.validity.is_none());
+            assert_dense(&rd);
+            assert!(rd.__delitem__(&key).is_err());
+            rd.clear().unwrap();
+            assert_eq!(rd.__len__(), 0);
+            assert_dense(&rd);
+        });
+    }
+
+    #[test]
+    fn test_mutating_schema_dict_detaches_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schema = make_schema(&["a", "b"]);
+            let mut rd =
+                RedDict::py_from_values(schema.bind(py), vec![Some(1.0), Some(2.0)]).unwrap();
+            let other =
+                RedDict::py_from_values(schema.bind(py), vec![Some(5.0), Some(6.0)]).unwrap();
+            rd.__delitem__(&PyString::new(py, "a")).unwrap();
+            assert_eq!(*schema.get().keys, ["a", "b"]);
+            assert!(!Arc::ptr_eq(&rd.index, &schema.get().index));
+            assert_eq!(
+                rd.py_add(
+                    &Bound::new(py, other).unwrap(),
+                    Some(0.0),
+                    "left",
+                    None,
+                    None,
+                    None
+                )
+                .unwrap()
+                .py_to_dict()
+                .get("b"),
+                Some(&Some(8.0))
+            );
+        });
+    }
+
+    #[test]
+    fn test_pop_setdefault_and_update() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let d2 = make_dict(py, &[("b", 20.0), ("c", 30.0)]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(d1.pop('a'), d1.pop('z', -1.0), d1.setdefault('b', 9.0), d1.setdefault('e'), \
+                   (d1.update(d2, f=6.0), d1.update({'g': 7.0}), d1.update([('h', None)]), \
+                    d1.to_dict)[-1])",
+            )
+            .unwrap();
+            let (popped, default, existing, inserted, dict): (
+                f64,
+                f64,
+                f64,
+                Option<f64>,
+                IndexMap<String, Option<f64>>,
+            ) = result.extract().unwrap();
+            assert_eq!(
+                (popped, default, existing, inserted),
+                (1.0, -1.0, 2.0, None)
+            );
+            let keys: Vec<&str> = dict.keys().map(String::as_str).collect();
+            assert_eq!(keys, ["b", "e", "c", "f", "g", "h"]);
+            assert_eq!(dict["b"], Some(20.0));
+            assert_eq!(dict["e"], None);
+            assert!(eval_with(py, &d1, &d2, c"d1.pop('zz')").is_err());
+        });
+    }
+
+    #[test]
+    fn test_update_with_same_layout_shares_values() {
+        Python::initialize();
+        Python::attach(|py| {
+            let schema = make_schema(&["a", "b"]);
+            let mut rd =
+                RedDict::py_from_values(schema.bind(py), vec![Some(1.0), Some(2.0)]).unwrap();
+            let other = RedDict::py_from_values(schema.bind(py), vec![Some(5.0), None]).unwrap();
+            rd.update_from(&other);
+            assert!(Arc::ptr_eq(&rd.values, &other.values));
+            assert_eq!(rd.py_values(), [Some(5.0), None]);
+            rd.__setitem__("a".into(), Some(0.0)).unwrap();
+            assert_eq!(other.py_values(), [Some(5.0), None]);
+        });
+    }
+
+    #[test]
+    fn test_eq_ignores_key_order() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", f64::NAN)]);
+            let d2 = make_dict(py, &[("b", f64::NAN), ("a", 1.0)]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(d1 == d2, d1 != d2, d1 == d1 + 1, d1 == {'a': 1.0, 'b': 0.0})",
+            )
+            .unwrap();
+            let flags: (bool, bool, bool, bool) = result.extract().unwrap();
+            assert_eq!(flags, (true, false, false, false));
+        });
+    }
+
+    #[test]
+    fn test_eq_compares_key_sets_and_nulls() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", Some(1.0)), ("b", None)]);
+            let d2 = make_dict(py, &[("a", Some(1.0)), ("c", None)]);
+            let d3 = make_dict(py, &[("a", Some(1.0)), ("b", Some(f64::NAN))]);
+            let d1 = Bound::new(py, d1).unwrap();
+            assert!(d1.borrow().__eq__(&d1));
+            assert!(!d1.borrow().__eq__(&Bound::new(py, d2).unwrap()));
+            assert!(!d1.borrow().__eq__(&Bound::new(py, d3).unwrap()));
+        });
+    }
+
+    #[test]
+    fn test_allclose_tolerances() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", f64::NAN)]);
+            let d2 = Bound::new(py, make_dict(py, &[("b", f64::NAN), ("a", 1.0 + 1e-9)])).unwrap();
+            assert!(!d1.allclose(&d2, 1e-5, 1e-8, false));
+            assert!(d1.allclose(&d2, 1e-5, 1e-8, true));
+            assert!(!d1.allclose(&d2, 0.0, 1e-10, true));
+            let d3 = Bound::new(py, make_dict(py, &[("a", 1.0)])).unwrap();
+            assert!(!d1.allclose(&d3, 1.0, 1.0, true));
+        });
+    }
+
+    #[test]
+    fn test_frozen_hash_is_order_independent() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 0.0), ("b", 2.0)]).freeze();
+            let d2 = make_dict(py, &[("b", 2.0), ("a", -0.0)]).freeze();
+            assert_eq!(d1.__hash__().unwrap(), d2.__hash__().unwrap());
+            let d3 = make_dict(py, &[("a", 0.0), ("b", 3.0)]).freeze();
+            assert_ne!(d1.__hash__().unwrap(), d3.__hash__().unwrap());
+            assert!(make_dict(py, &[("a", 0.0)]).__hash__().is_err());
+
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(len({d1, d2}), {d1: 'hit'}[d2], (d1 * 2).frozen, d1.frozen)",
+            )
+            .unwrap();
+            let (len, hit, derived, frozen): (usize, String, bool, bool) =
+                result.extract().unwrap();
+            assert_eq!((len, hit.as_str(), derived, frozen), (1, "hit", true, true));
+        });
+    }
+
+    #[test]
+    fn test_frozen_refuses_mutation() {
+        Python::initialize();
+        Python::attach(|py| {
+            let frozen = make_dict(py, &[("a", 1.0)]).freeze();
+            let other = make_dict(py, &[("a", 2.0)]);
+            for expr in [
+                c"d1.__setitem__('a', 2.0)",
+                c"d1.__delitem__('a')",
+                c"d1.pop('a')",
+                c"d1.setdefault('z', 1.0)",
+                c"d1.update(d2)",
+                c"d1.clear()",
+                c"d1.iadd(d2)",
+                c"d1.iadd_scalar(1.0)",
+            ] {
+                let err = eval_with(py, &frozen, &other, expr).err().unwrap();
+                assert!(err.is_instance_of::<PyTypeError>(py), "{expr:?}");
+            }
+            for expr in [c"d1.__iadd__(d2)", c"d1.__imul__(2.0)"] {
+                let result = eval_with(py, &frozen, &other, expr).unwrap();
+                assert!(result.is(py.NotImplemented()), "{expr:?}");
+            }
+
+            // Augmented assignment rebinds the name instead, like a tuple's.
+            let locals = PyDict::new(py);
+            let original = Py::new(py, frozen.clone()).unwrap();
+            locals.set_item("d1", &original).unwrap();
+            locals
+                .set_item("d2", Py::new(py, other.clone()).unwrap())
+                .unwrap();
+            py.run(c"d1 += d2\nd1 *= 10.0", None, Some(&locals))
+                .unwrap();
+            let rebound = locals.get_item("d1").unwrap().unwrap();
+            assert!(!rebound.is(&original));
+            let rebound = rebound.cast::<RedDict>().unwrap().borrow();
+            assert_eq!(*rebound.values, [30.0]);
+            assert!(rebound.frozen);
+            assert_eq!(*original.borrow(py).values, [1.0]);
+            assert_eq!(*frozen.values, [1.0]);
+            let result = eval_dict(py, &frozen, &other, c"d1 + d2");
+            assert!(result.frozen);
+            assert!(other.freeze().add_scalar(1.0).frozen);
+        });
+    }
+
+    /// Imports a test dependency, failing the test when it is missing;
+    /// `pip install -e '.[test]'` installs them all.
+    fn require<'py>(py: Python<'py>, module: &str) -> Bound<'py, PyModule> {
+        py.import(module).unwrap_or_else(|err| {
+            panic!("{module} is a test dependency (pip install -e '.[test]'): {err}")
+        })
+    }
+
+    /// Makes `import redbear` find this module, as pickle needs to.
+    fn install_module(py: Python<'_>) {
+        let modules = py.import("sys").unwrap().getattr("modules").unwrap();
+        if !modules.contains("redbear").unwrap() {
+            let m = PyModule::new(py, "redbear").unwrap();
+            redbear(&m).unwrap();
+            modules.set_item("redbear", m).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_pickle_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            install_module(py);
+            let mut rd = make_dict(py, &[("a", 1.5), ("b", f64::NAN), ("c", -0.0)]);
+            rd.__setitem__("d".to_string(), None).unwrap();
+            let frozen = make_dict(py, &[("x", 2.0)]).freeze();
+            let result = eval_with(
+                py,
+                &rd,
+                &frozen,
+                c"[__import__('pickle').loads(__import__('pickle').dumps(d, p)) for d, p in [(d1, 2), (d1, 5), (d2, 5)]]",
+            )
+            .unwrap();
+            let copies: Vec<Bound<RedDict>> = result.extract().unwrap();
+            for copy in &copies[..2] {
+                let copy = copy.borrow();
+                assert_eq!(*copy.keys, ["a", "b", "c", "d"]);
+                assert!(copy.__eq__(&Bound::new(py, rd.clone()).unwrap()));
+                assert!(copy.values[2].is_sign_negative());
+                assert!(!copy.frozen);
+            }
+            let copy = copies[2].borrow();
+            assert!(copy.frozen);
+            assert_eq!(copy.py_to_dict().get("x"), Some(&Some(2.0)));
+        });
+    }
+
+    #[test]
+    fn test_unpickled_dicts_share_layout() {
+        Python::initialize();
+        Python::attach(|py| {
+            install_module(py);
+            let rd = make_dict(py, &[("unpickled-a", 1.0), ("unpickled-b", 2.0)]);
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(__import__('pickle').loads(__import__('pickle').dumps(d1)), __import__('pickle').loads(__import__('pickle').dumps(d2 * 2)))",
+            )
+            .unwrap();
+            let (first, second): (Bound<RedDict>, Bound<RedDict>) = result.extract().unwrap();
+            let (first, second) = (first.borrow(), second.borrow());
+            assert!(Arc::ptr_eq(&first.keys, &second.keys));
+            assert!(Arc::ptr_eq(&first.index, &second.index));
+            assert_eq!(*second.values, [2.0, 4.0]);
+        });
+    }
+
+    #[test]
+    fn test_from_state_rejects_bad_state() {
+        Python::initialize();
+        Python::attach(|_py| {
+            let keys = vec!["a".to_string()];
+            assert!(RedDict::_from_state(keys.clone(), &[0; 7], None, false).is_err());
+            assert!(RedDict::_from_state(keys.clone(), &[0; 8], Some(&[]), false).is_err());
+            let dupes = vec!["a".to_string(), "a".to_string()];
+            assert!(RedDict::_from_state(dupes, &[0; 16], None, false).is_err());
+            let rd = RedDict::_from_state(keys, &1.0f64.to_le_bytes(), Some(&[0]), false).unwrap();
+            assert_eq!(rd.py_to_dict().get("a"), Some(&None));
+        });
+    }
+
+    #[test]
+    fn test_copy_shares_storage() {
         Python::initialize();
         Python::attach(|py| {
             let rd = make_dict(py, &[("a", 1.0)]);
-            let _ = rd.reset(100.0);
-            assert_eq!(rd.to_dict().get("a"), Some(&1.0));
+            let frozen = rd.freeze();
+            let result = eval_with(
+                py,
+                &rd,
+                &frozen,
+                c"(__import__('copy').copy(d1), __import__('copy').deepcopy(d1), __import__('copy').copy(d2) is d2, __import__('copy').deepcopy(d2) is d2)",
+            )
+            .unwrap();
+            let (shallow, deep, same, deep_same): (Bound<RedDict>, Bound<RedDict>, bool, bool) =
+                result.extract().unwrap();
+            assert!(same && deep_same);
+            assert!(Arc::ptr_eq(&shallow.borrow().values, &rd.values));
+            assert!(Arc::ptr_eq(&deep.borrow().values, &rd.values));
+            shallow
+                .borrow_mut()
+                .__setitem__("a".to_string(), Some(5.0))
+                .unwrap();
+            assert_eq!(*rd.values, [1.0]);
+            assert_eq!(*deep.borrow().values, [1.0]);
         });
     }
 
     #[test]
-    fn test_multiply_does_not_modify_operands() {
+    fn test_save_and_load_round_trip() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
-            let right = make_dict(py, &[("b", 10.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let _ = left.multiply(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(left.to_dict().get("a"), Some(&1.0));
-            assert_eq!(left.to_dict().get("b"), Some(&2.0));
-            assert_eq!(right.to_dict().get("b"), Some(&10.0));
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let path = std::env::temp_dir().join(format!("redbear-{}.rbd", std::process::id()));
+            rd.save(py, path.clone(), true).unwrap();
+            let loaded = RedDict::load(path.clone()).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(loaded.py_to_dict(), rd.py_to_dict());
+            assert!(RedDict::load(path)
+                .err()
+                .unwrap()
+                .is_instance_of::<pyo3::exceptions::PyFileNotFoundError>(py));
+
+            let two = format::encode(py, &[rd.clone(), rd.clone()], false).unwrap();
+            let err = RedDict::from_bytes(&two).err().unwrap();
+            assert!(err.to_string().contains("found 2"));
+            let bytes = rd.to_bytes(py, true).unwrap();
+            assert_eq!(
+                RedDict::from_bytes(bytes.as_bytes()).unwrap().py_to_dict(),
+                rd.py_to_dict()
+            );
         });
     }
 
     #[test]
-    fn test_divide_does_not_modify_operands() {
+    fn test_buffer_exports_read_only_values() {
         Python::initialize();
         Python::attach(|py| {
-            let left = make_dict(py, &[("a", 10.0), ("b", 6.0)]);
-            let right = make_dict(py, &[("b", 2.0)]);
-            let py_right = Py::new(py, right.clone()).unwrap();
-            let _ = left.divide(py_right.bind(py), 1.0).unwrap();
-            assert_eq!(left.to_dict().get("a"), Some(&10.0));
-            assert_eq!(left.to_dict().get("b"), Some(&6.0));
+            let mut rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            rd.__setitem__("c".to_string(), None).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(m := memoryview(d1), m.format, m.shape, m.readonly, d1.iadd_scalar(1.0), m.tolist(), d1.values())",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            let format: String = item(1).extract().unwrap();
+            let shape: (usize,) = item(2).extract().unwrap();
+            let readonly: bool = item(3).extract().unwrap();
+            let exported: Vec<f64> = item(5).extract().unwrap();
+            let values: Vec<Option<f64>> = item(6).extract().unwrap();
+            assert_eq!((format.as_str(), shape, readonly), ("d", (3,), true));
+            assert_eq!(exported[..2], [1.0, 2.0]);
+            assert!(exported[2].is_nan());
+            assert_eq!(values, [Some(2.0), Some(3.0), None]);
+        });
+    }
+
+    #[test]
+    fn test_from_arrays_reads_buffers_and_sequences() {
+        Python::initialize();
+        Python::attach(|py| {
+            let rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(d1.from_arrays(['x', 'y'], __import__('array').array('d', [1.0, 2.5])), d1.from_arrays(d1.keys(), d1), d1.from_arrays(('p',), [None]))",
+            )
+            .unwrap();
+            let (from_buffer, from_dict, from_list): (
+                Bound<RedDict>,
+                Bound<RedDict>,
+                Bound<RedDict>,
+            ) = result.extract().unwrap();
+            assert_eq!(*from_buffer.borrow().keys, ["x", "y"]);
+            assert_eq!(*from_buffer.borrow().values, [1.0, 2.5]);
+            assert!(from_dict
+                .borrow()
+                .__eq__(&Bound::new(py, rd.clone()).unwrap()));
+            assert_eq!(from_list.borrow().py_to_dict().get("p"), Some(&None));
+
+            for expr in [
+                c"d1.from_arrays(['x'], [1.0, 2.0])",
+                c"d1.from_arrays(['x', 'x'], [1.0, 2.0])",
+                c"d1.from_arrays([1], [1.0])",
+            ] {
+                assert!(eval_with(py, &rd, &rd, expr).is_err(), "{expr:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_numpy_array_copy_false_never_copies() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "numpy");
+            let rd = make_dict(py, &[("a", 1.0)]);
+            let view = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"__import__('numpy').asarray(d1, dtype='float64', copy=False).flags.writeable",
+            )
+            .unwrap();
+            assert!(!view.extract::<bool>().unwrap());
+            for expr in [
+                c"d1.__array__('float32', copy=False)",
+                c"__import__('numpy').asarray(d1, dtype='float32', copy=False)",
+            ] {
+                let err = eval_with(py, &rd, &rd, expr).unwrap_err();
+                assert!(err.is_instance_of::<PyValueError>(py), "{expr:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_numpy_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "numpy");
+            let mut rd = make_dict(py, &[("b", 2.0), ("a", 1.0)]);
+            rd.__setitem__("long key".to_string(), Some(3.0)).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(np := __import__('numpy'), np.asarray(d1).tolist(), np.asarray(d1).flags.writeable, d1.__array__(copy=True).flags.writeable, d1.__array__('float32').dtype.name, d1.keys_array().tolist(), d1.from_arrays(d1.keys_array(), np.asarray(d1)) == d1)",
+            )
+            .unwrap();
+            let (_, values, view_writeable, copy_writeable, dtype, keys, round_trip): (
+                Bound<PyAny>,
+                Vec<f64>,
+                bool,
+                bool,
+                String,
+                Vec<String>,
+                bool,
+            ) = result.extract().unwrap();
+            assert_eq!(values, [2.0, 1.0, 3.0]);
+            assert!(!view_writeable && copy_writeable);
+            assert_eq!(dtype, "float32");
+            assert_eq!(keys, ["b", "a", "long key"]);
+            assert!(round_trip);
+            let keys_kind = eval_with(py, &rd, &rd, c"d1.keys_array().dtype.kind").unwrap();
+            assert_eq!(keys_kind.extract::<String>().unwrap(), "U");
+        });
+    }
+
+    #[test]
+    fn test_array_ufunc_aligns_operands() {
+        Python::initialize();
+        Python::attach(|py| {
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 2.0), ("c", 3.0)]);
+            let d2 = make_dict(py, &[("b", 20.0), ("a", 10.0)]);
+            // Stand-ins for numpy ufuncs, working on the memoryviews they get.
+            let locals = PyDict::new(py);
+            locals
+                .set_item("d1", Py::new(py, d1.clone()).unwrap())
+                .unwrap();
+            locals.set_item("d2", Py::new(py, d2).unwrap()).unwrap();
+            py.run(
+                c"import array
+class Add:
+    nout = 1
+    def __call__(self, a, b):
+        a = a.tolist() if isinstance(a, memoryview) else [a] * len(b)
+        b = b.tolist() if isinstance(b, memoryview) else [b] * len(a)
+        return array.array('d', [x + y for x, y in zip(a, b)])
+class Neg:
+    nout = 1
+    def __call__(self, a):
+        return [-x for x in a.tolist()]
+add, neg = Add(), Neg()
+",
+                Some(&locals),
+                None,
+            )
+            .unwrap();
+            let eval = |expr: &CStr| py.eval(expr, Some(&locals), None).unwrap();
+
+            let both = eval(c"d1.__array_ufunc__(add, '__call__', d1, d2).to_dict");
+            let both: IndexMap<String, Option<f64>> = both.extract().unwrap();
+            assert_eq!(
+                both.into_iter().collect::<Vec<_>>(),
+                [
+                    ("a".to_string(), Some(11.0)),
+                    ("b".to_string(), Some(22.0)),
+                    ("c".to_string(), None)
+                ]
+            );
+            let left: Vec<Option<f64>> =
+                eval(c"d1.__array_ufunc__(add, '__call__', 1.0, d1).values()")
+                    .extract()
+                    .unwrap();
+            assert_eq!(left, [Some(2.0), Some(3.0), Some(4.0)]);
+            let negated = eval(c"d1.__array_ufunc__(neg, '__call__', d1)");
+            let negated = negated.cast::<RedDict>().unwrap().borrow();
+            assert_eq!(*negated.values, [-1.0, -2.0, -3.0]);
+            assert!(Arc::ptr_eq(&negated.index, &d1.index));
+
+            let same: bool = eval(c"d1.__array_ufunc__(add, '__call__', d1, 1.0, out=(d1,)) is d1")
+                .extract()
+                .unwrap();
+            assert!(same);
+            let updated: Vec<Option<f64>> = eval(c"d1.values()").extract().unwrap();
+            assert_eq!(updated, [Some(2.0), Some(3.0), Some(4.0)]);
+
+            // `out=` keeps its own key order, and must hold the result's keys.
+            let d3 = make_dict(py, &[("c", 30.0), ("b", 20.0), ("a", 10.0)]);
+            locals.set_item("d3", Py::new(py, d3).unwrap()).unwrap();
+            let updated = eval(c"d1.__array_ufunc__(add, '__call__', d3, 1.0, out=(d1,)).to_dict");
+            let updated: IndexMap<String, Option<f64>> = updated.extract().unwrap();
+            assert_eq!(
+                updated.into_iter().collect::<Vec<_>>(),
+                [
+                    ("a".to_string(), Some(11.0)),
+                    ("b".to_string(), Some(21.0)),
+                    ("c".to_string(), Some(31.0))
+                ]
+            );
+            let err = py
+                .eval(
+                    c"d1.__array_ufunc__(add, '__call__', d2, 1.0, out=(d1,))",
+                    Some(&locals),
+                    None,
+                )
+                .unwrap_err();
+            assert!(err.is_instance_of::<PyValueError>(py));
+            let unchanged: Vec<Option<f64>> = eval(c"d1.values()").extract().unwrap();
+            assert_eq!(unchanged, [Some(11.0), Some(21.0), Some(31.0)]);
+
+            for expr in [
+                c"d1.__array_ufunc__(add, 'reduce', d1)",
+                c"d1.__array_ufunc__(add, '__call__', d1, 'x')",
+                c"d1.__array_ufunc__(add, '__call__', d1, 1.0, out=([],))",
+            ] {
+                assert!(eval(expr).is(py.NotImplemented()), "{expr:?}");
+            }
+            let frozen = py
+                .eval(
+                    c"d1.__array_ufunc__(add, '__call__', d1, 1.0, out=(d1.freeze(),))",
+                    Some(&locals),
+                    None,
+                )
+                .err()
+                .unwrap();
+            assert!(frozen.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_numpy_ufuncs_return_reddicts() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "numpy");
+            let d1 = make_dict(py, &[("a", 1.0), ("b", 4.0)]);
+            let d2 = make_dict(py, &[("b", 5.0), ("a", 0.0)]);
+            let result = eval_with(
+                py,
+                &d1,
+                &d2,
+                c"(np := __import__('numpy'), np.sqrt(d1).values(), np.maximum(d1, d2).values(), np.add(3.0, d1).values(), np.add(d1, d2, out=d1) is d1, d1.values())",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            let sqrt: Vec<Option<f64>> = item(1).extract().unwrap();
+            let maximum: Vec<Option<f64>> = item(2).extract().unwrap();
+            let added: Vec<Option<f64>> = item(3).extract().unwrap();
+            let same: bool = item(4).extract().unwrap();
+            let updated: Vec<Option<f64>> = item(5).extract().unwrap();
+            assert_eq!(sqrt, [Some(1.0), Some(2.0)]);
+            assert_eq!(maximum, [Some(1.0), Some(5.0)]);
+            assert_eq!(added, [Some(4.0), Some(7.0)]);
+            assert!(same);
+            assert_eq!(updated, [Some(1.0), Some(9.0)]);
+        });
+    }
+
+    #[test]
+    fn test_arrow_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            let mut rd = make_dict(py, &[("a", 1.0), ("é", -2.5)]);
+            rd.__setitem__("c".to_string(), None).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(d1.from_arrow(d1), d1.from_arrow(type('Stream', (), {'__arrow_c_stream__': lambda self, requested_schema=None, d=d1: d.__arrow_c_stream__()})()), d1.__arrow_c_array__(), d1.__arrow_c_stream__())",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            for i in [0, 1] {
+                let copy = item(i).cast_into::<RedDict>().unwrap();
+                assert!(copy.borrow().__eq__(&Bound::new(py, rd.clone()).unwrap()));
+                assert_eq!(*copy.borrow().keys, ["a", "é", "c"]);
+            }
+            // Unconsumed capsules release their exports when collected.
+            drop(result);
+            py.import("gc").unwrap().call_method0("collect").unwrap();
+            assert_eq!(Arc::strong_count(&rd.values), 1);
+
+            for expr in [
+                c"d1.from_arrow(1.0)",
+                c"d1.from_arrow(type('Bad', (), {'__arrow_c_array__': lambda self: (1, 2)})())",
+            ] {
+                assert!(eval_with(py, &rd, &rd, expr).is_err(), "{expr:?}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_pyarrow_interchange() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "pyarrow");
+            let mut rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            rd.__setitem__("c".to_string(), None).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(pa := __import__('pyarrow'), pa.record_batch(d1).to_pydict(), pa.table(d1).num_rows, d1.from_arrow(pa.table({'k': pa.array(['x', 'y'], pa.large_string()), 'v': pa.array([1, None], pa.int32())})).to_dict)",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            let batch: HashMap<String, Vec<Option<Bound<PyAny>>>> = item(1).extract().unwrap();
+            assert_eq!(batch["key"].len(), 3);
+            assert!(batch["value"][2].is_none());
+            assert_eq!(item(2).extract::<usize>().unwrap(), 3);
+            let imported: IndexMap<String, Option<f64>> = item(3).extract().unwrap();
+            assert_eq!(imported.get("x"), Some(&Some(1.0)));
+            assert_eq!(imported.get("y"), Some(&None));
+        });
+    }
+
+    #[test]
+    fn test_from_arrow_reads_string_views() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "pyarrow");
+            require(py, "polars");
+            let locals = PyDict::new(py);
+            locals
+                .set_item("RedDict", py.get_type::<RedDict>())
+                .unwrap();
+            py.run(
+                c"import polars as pl
+import pyarrow as pa
+frame = pl.DataFrame({'key': ['x', 'a key longer than twelve bytes'], 'value': [1.5, None]})
+views = frame.to_arrow(compat_level=pl.CompatLevel.newest())
+is_view = views.schema.field('key').type == pa.string_view()
+from_polars = RedDict.from_arrow(frame).to_dict
+from_views = RedDict.from_arrow(views).to_dict
+",
+                None,
+                Some(&locals),
+            )
+            .unwrap();
+            let get = |name: &str| locals.get_item(name).unwrap().unwrap();
+            assert!(get("is_view").extract::<bool>().unwrap());
+            let expected = [
+                ("x".to_string(), Some(1.5)),
+                ("a key longer than twelve bytes".to_string(), None),
+            ];
+            for name in ["from_polars", "from_views"] {
+                let imported: IndexMap<String, Option<f64>> = get(name).extract().unwrap();
+                assert_eq!(imported.into_iter().collect::<Vec<_>>(), expected, "{name}");
+            }
+        });
+    }
+
+    #[test]
+    fn test_pandas_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "pandas");
+            let mut rd = make_dict(py, &[("b", 2.0), ("a", 1.0)]);
+            rd.__setitem__("c".to_string(), None).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(s := d1.to_pandas(), list(s.index), bool(s.isna().iloc[2]), d1.from_pandas(s).to_dict, d1.from_pandas(__import__('pandas').Series([1, None], index=['x', 'y'], dtype='Int64')).to_dict)",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            assert_eq!(item(1).extract::<Vec<String>>().unwrap(), ["b", "a", "c"]);
+            assert!(item(2).extract::<bool>().unwrap());
+            let back: IndexMap<String, Option<f64>> = item(3).extract().unwrap();
+            assert_eq!(
+                back.into_iter().collect::<Vec<_>>(),
+                [
+                    ("b".to_string(), Some(2.0)),
+                    ("a".to_string(), Some(1.0)),
+                    ("c".to_string(), None)
+                ]
+            );
+            let nullable: IndexMap<String, Option<f64>> = item(4).extract().unwrap();
+            assert_eq!(nullable.get("x"), Some(&Some(1.0)));
+            assert_eq!(nullable.get("y"), Some(&None));
+        });
+    }
+
+    #[test]
+    fn test_from_pandas_rejects_bad_labels() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "pandas");
+            let rd = make_dict(py, &[("a", 1.0)]);
+            let duplicate = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"d1.from_pandas(__import__('pandas').Series([1.0, 2.0], index=['a', 'a']))",
+            )
+            .unwrap_err();
+            assert!(duplicate.is_instance_of::<PyValueError>(py));
+            assert!(duplicate.to_string().contains("'a'"));
+            let numeric = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"d1.from_pandas(__import__('pandas').Series([1.0, 2.0]))",
+            )
+            .unwrap_err();
+            assert!(numeric.is_instance_of::<PyTypeError>(py));
+        });
+    }
+
+    #[test]
+    fn test_polars_round_trip() {
+        Python::initialize();
+        Python::attach(|py| {
+            require(py, "polars");
+            let mut rd = make_dict(py, &[("a", 1.0), ("b", 2.0)]);
+            rd.__setitem__("c".to_string(), None).unwrap();
+            let result = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"(pl := __import__('polars'), d1.to_polars().columns, d1.from_polars(d1.to_polars()).to_dict, d1.from_polars(pl.DataFrame({'n': ['x', 'y'], 's': [1, None]}), 'n', 's').to_dict)",
+            )
+            .unwrap();
+            let item = |i| result.get_item(i).unwrap();
+            assert_eq!(item(1).extract::<Vec<String>>().unwrap(), ["key", "value"]);
+            let back: IndexMap<String, Option<f64>> = item(2).extract().unwrap();
+            assert_eq!(back.len(), 3);
+            assert_eq!(back.get("c"), Some(&None));
+            let named: IndexMap<String, Option<f64>> = item(3).extract().unwrap();
+            assert_eq!(named.get("x"), Some(&Some(1.0)));
+            assert_eq!(named.get("y"), Some(&None));
+
+            let duplicate = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"d1.from_polars(__import__('polars').DataFrame({'key': ['a', 'a'], 'value': [1.0, 2.0]}))",
+            )
+            .unwrap_err();
+            assert!(duplicate.is_instance_of::<PyValueError>(py));
+            let numeric = eval_with(
+                py,
+                &rd,
+                &rd,
+                c"d1.from_polars(__import__('polars').DataFrame({'key': [1, 2], 'value': [1.0, 2.0]}))",
+            )
+            .unwrap_err();
+            assert!(numeric.is_instance_of::<PyTypeError>(py));
         });
     }
 }
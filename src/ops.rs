@@ -0,0 +1,174 @@
+//! Element-wise kernels that need more than a single `f64` method call.
+
+/// Python's `a % b` for floats: the result takes the sign of `b`.
+pub(crate) fn py_mod(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        return f64::NAN;
+    }
+    let r = a % b;
+    if r == 0.0 {
+        0.0_f64.copysign(b)
+    } else if (r < 0.0) != (b < 0.0) {
+        r + b
+    } else {
+        r
+    }
+}
+
+/// Python's `a // b` for floats, consistent with [`py_mod`] so that
+/// `a == (a // b) * b + a % b` up to rounding.
+pub(crate) fn py_floor_div(a: f64, b: f64) -> f64 {
+    if b == 0.0 {
+        return a / b;
+    }
+    let r = a % b;
+    let mut div = (a - r) / b;
+    if r != 0.0 && (r < 0.0) != (b < 0.0) {
+        div -= 1.0;
+    }
+    if div == 0.0 {
+        return 0.0_f64.copysign(a / b);
+    }
+    // `div` is mathematically an integer; snap away rounding error.
+    let floor = div.floor();
+    if div - floor > 0.5 {
+        floor + 1.0
+    } else {
+        floor
+    }
+}
+
+/// Rounds half to even at `ndigits` decimal places, like Python's `round`.
+/// Negative `ndigits` round to tens, hundreds, and so on.
+pub(crate) fn round(v: f64, ndigits: i32) -> f64 {
+    if ndigits == 0 {
+        return v.round_ties_even();
+    }
+    let scale = 10f64.powi(ndigits.abs());
+    // Past 10**308 every finite value rounds to a signed zero.
+    if ndigits < 0 && scale.is_infinite() && v.is_finite() {
+        return 0.0_f64.copysign(v);
+    }
+    let rounded = if ndigits > 0 {
+        (v * scale).round_ties_even() / scale
+    } else {
+        (v / scale).round_ties_even() * scale
+    };
+    // Scaling overflows for huge values, which have no fractional digits.
+    if rounded.is_finite() {
+        rounded
+    } else {
+        v
+    }
+}
+
+/// The larger of `a` and `b`, or NaN if either is NaN, like `numpy.maximum`.
+/// `f64::max` would skip the NaN instead, as `numpy.fmax` does.
+pub(crate) fn maximum(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.max(b)
+    }
+}
+
+/// The smaller of `a` and `b`, or NaN if either is NaN, like `numpy.minimum`.
+pub(crate) fn minimum(a: f64, b: f64) -> f64 {
+    if a.is_nan() || b.is_nan() {
+        f64::NAN
+    } else {
+        a.min(b)
+    }
+}
+
+/// -1.0, 0.0 or 1.0 by the sign of `v`; NaN stays NaN.
+pub(crate) fn sign(v: f64) -> f64 {
+    if v == 0.0 || v.is_nan() {
+        v
+    } else {
+        v.signum()
+    }
+}
+
+/// Clamps `v` into `[lo, hi]`, leaving NaN untouched.
+pub(crate) fn clip(v: f64, lo: f64, hi: f64) -> f64 {
+    if v < lo {
+        lo
+    } else if v > hi {
+        hi
+    } else {
+        v
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_py_mod_follows_divisor_sign() {
+        assert_eq!(py_mod(7.0, 3.0), 1.0);
+        assert_eq!(py_mod(-7.0, 3.0), 2.0);
+        assert_eq!(py_mod(7.0, -3.0), -2.0);
+        assert_eq!(py_mod(-7.0, -3.0), -1.0);
+        assert_eq!(py_mod(5.5, 2.0), 1.5);
+        assert!(py_mod(1.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn test_py_floor_div_rounds_down() {
+        assert_eq!(py_floor_div(7.0, 2.0), 3.0);
+        assert_eq!(py_floor_div(-7.0, 2.0), -4.0);
+        assert_eq!(py_floor_div(7.0, -2.0), -4.0);
+        assert_eq!(py_floor_div(-7.0, -2.0), 3.0);
+        assert_eq!(py_floor_div(1.0, 0.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_py_mod_and_floor_div_agree() {
+        for (a, b) in [(7.5, 2.0), (-7.5, 2.0), (0.3, 0.1), (-1e10, 3.0)] {
+            let q = py_floor_div(a, b);
+            let r = py_mod(a, b);
+            assert!((q * b + r - a).abs() < 1e-9, "{a} {b}");
+        }
+    }
+
+    #[test]
+    fn test_round_half_to_even() {
+        assert_eq!(round(0.5, 0), 0.0);
+        assert_eq!(round(1.5, 0), 2.0);
+        assert_eq!(round(-2.5, 0), -2.0);
+        assert_eq!(round(1.2345, 2), 1.23);
+        assert_eq!(round(1250.0, -2), 1200.0);
+        assert_eq!(round(1e308, 10), 1e308);
+    }
+
+    #[test]
+    fn test_round_to_huge_negative_digits_gives_zero() {
+        assert_eq!(round(123.0, -400).to_bits(), 0.0_f64.to_bits());
+        assert_eq!(round(-1e308, -400).to_bits(), (-0.0_f64).to_bits());
+        assert_eq!(round(f64::INFINITY, -400), f64::INFINITY);
+        assert!(round(f64::NAN, -400).is_nan());
+    }
+
+    #[test]
+    fn test_maximum_and_minimum_propagate_nan() {
+        assert_eq!(maximum(1.0, 2.0), 2.0);
+        assert_eq!(minimum(1.0, 2.0), 1.0);
+        assert_eq!(maximum(1.0, f64::NEG_INFINITY), 1.0);
+        assert!(maximum(f64::NAN, 1.0).is_nan());
+        assert!(maximum(1.0, f64::NAN).is_nan());
+        assert!(minimum(f64::NAN, 1.0).is_nan());
+        assert!(minimum(1.0, f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn test_sign_and_clip_keep_nan() {
+        assert_eq!(sign(-3.0), -1.0);
+        assert_eq!(sign(0.0), 0.0);
+        assert!(sign(f64::NAN).is_nan());
+        assert_eq!(clip(5.0, 0.0, 1.0), 1.0);
+        assert_eq!(clip(-5.0, 0.0, 1.0), 0.0);
+        assert!(clip(f64::NAN, 0.0, 1.0).is_nan());
+    }
+}
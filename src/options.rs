@@ -0,0 +1,53 @@
+//! Module-level defaults, exposed to Python as `set_options` / `get_options`.
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use pyo3::{prelude::*, types::PyDict};
+
+static STRICT: AtomicBool = AtomicBool::new(false);
+static REPR_MAX_ITEMS: AtomicUsize = AtomicUsize::new(10);
+
+/// Whether binary operations raise on mismatched keys when the caller does
+/// not pass `strict` explicitly.
+pub(crate) fn strict() -> bool {
+    STRICT.load(Ordering::Relaxed)
+}
+
+/// Resolves a per-call `strict` argument against the module default.
+pub(crate) fn resolve_strict(strict: Option<bool>) -> bool {
+    strict.unwrap_or_else(self::strict)
+}
+
+/// How many entries `repr`, `str` and the Jupyter view show before eliding
+/// the middle of a RedDict.
+pub(crate) fn repr_max_items() -> usize {
+    REPR_MAX_ITEMS.load(Ordering::Relaxed)
+}
+
+/// Updates module-level defaults. Options left as `None` are unchanged.
+///
+/// # Examples
+///
+/// ```python
+/// >>> rb.set_options(strict=True, repr_max_items=20)
+/// >>> rb.get_options()
+/// {'strict': True, 'repr_max_items': 20}
+/// ```
+#[pyfunction]
+#[pyo3(signature = (*, strict=None, repr_max_items=None))]
+pub(crate) fn set_options(strict: Option<bool>, repr_max_items: Option<usize>) {
+    if let Some(strict) = strict {
+        STRICT.store(strict, Ordering::Relaxed);
+    }
+    if let Some(max_items) = repr_max_items {
+        REPR_MAX_ITEMS.store(max_items, Ordering::Relaxed);
+    }
+}
+
+/// Returns the current module-level defaults as a dict.
+#[pyfunction]
+pub(crate) fn get_options(py: Python) -> PyResult<Bound<PyDict>> {
+    let options = PyDict::new(py);
+    options.set_item("strict", strict())?;
+    options.set_item("repr_max_items", repr_max_items())?;
+    Ok(options)
+}
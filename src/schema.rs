@@ -0,0 +1,179 @@
+//! Shared key layouts.
+//!
+//! A [`KeySchema`] is an ordered set of keys that several `RedDict`s can be
+//! built against. Every dict built from the same schema shares its `keys` and
+//! `index` `Arc`s, so binary operations between them always take the
+//! `Arc::ptr_eq` fast path in `merge`, regardless of how the source data was
+//! ordered.
+//!
+//! Schemas are interned: building a schema from a key sequence equal to one
+//! that is still alive returns the existing layout instead of a new one.
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, LazyLock, Mutex, PoisonError, Weak};
+
+use pyo3::{exceptions::PyValueError, prelude::*, types::PyString};
+
+use crate::KeyIterator;
+
+/// An interned, ordered key layout that RedDicts can share.
+///
+/// # Examples
+///
+/// ```python
+/// >>> s = rb.KeySchema(["a", "b"])
+/// >>> d1 = rb.RedDict({"b": 2.0, "a": 1.0}, schema=s)
+/// >>> d2 = rb.RedDict.from_values(s, [10.0, 20.0])
+/// >>> d1.add(d2).to_dict
+/// {'a': 11.0, 'b': 22.0}
+/// ```
+#[pyclass(frozen, skip_from_py_object)]
+pub(crate) struct KeySchema {
+    pub(crate) keys: Arc<Vec<String>>,
+    pub(crate) index: Arc<HashMap<String, usize>>,
+}
+
+#[pymethods]
+impl KeySchema {
+    /// Creates (or looks up) the schema for the given key order.
+    #[new]
+    pub(crate) fn new(keys: Vec<String>) -> PyResult<Self> {
+        let mut index = HashMap::with_capacity(keys.len());
+        for (pos, key) in keys.iter().enumerate() {
+            if index.insert(key.clone(), pos).is_some() {
+                return Err(PyValueError::new_err(format!(
+                    "duplicate key {key:?} in schema"
+                )));
+            }
+        }
+        Ok(Self::interned(Arc::new(keys), Arc::new(index)))
+    }
+
+    fn __len__(&self) -> usize {
+        self.keys.len()
+    }
+
+    fn __contains__(&self, key: &Bound<PyAny>) -> bool {
+        key.cast::<PyString>()
+            .ok()
+            .and_then(|k| k.to_str().ok().map(|k| self.index.contains_key(k)))
+            .unwrap_or(false)
+    }
+
+    fn __iter__(&self) -> KeyIterator {
+        KeyIterator {
+            keys: Arc::clone(&self.keys),
+            pos: 0,
+        }
+    }
+
+    /// Returns the keys in schema order.
+    fn keys(&self) -> Vec<&str> {
+        self.keys.iter().map(String::as_str).collect()
+    }
+}
+
+impl KeySchema {
+    /// Wraps an existing layout, swapping in the interned copy if an equal
+    /// one is alive, or registering this one otherwise.
+    pub(crate) fn interned(keys: Arc<Vec<String>>, index: Arc<HashMap<String, usize>>) -> Self {
+        let (keys, index) = INTERNER
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .intern(keys, index);
+        Self { keys, index }
+    }
+}
+
+type Entry = (Weak<Vec<String>>, Weak<HashMap<String, usize>>);
+
+/// Live layouts bucketed by a fingerprint of their key order.
+///
+/// Only weak references are held, so a layout is dropped as soon as the last
+/// RedDict or KeySchema using it goes away. Dead entries are swept whenever
+/// the table doubles in size.
+struct Interner {
+    buckets: HashMap<u64, Vec<Entry>>,
+    sweep_at: usize,
+}
+
+static INTERNER: LazyLock<Mutex<Interner>> = LazyLock::new(|| {
+    Mutex::new(Interner {
+        buckets: HashMap::new(),
+        sweep_at: 64,
+    })
+});
+
+impl Interner {
+    fn intern(
+        &mut self,
+        keys: Arc<Vec<String>>,
+        index: Arc<HashMap<String, usize>>,
+    ) -> (Arc<Vec<String>>, Arc<HashMap<String, usize>>) {
+        let mut hasher = DefaultHasher::new();
+        keys.hash(&mut hasher);
+        let bucket = self.buckets.entry(hasher.finish()).or_default();
+
+        bucket.retain(|(k, i)| k.strong_count() > 0 && i.strong_count() > 0);
+        for (k, i) in bucket.iter() {
+            if let (Some(k), Some(i)) = (k.upgrade(), i.upgrade()) {
+                if Arc::ptr_eq(&k, &keys) || k == keys {
+                    return (k, i);
+                }
+            }
+        }
+        bucket.push((Arc::downgrade(&keys), Arc::downgrade(&index)));
+
+        if self.buckets.len() >= self.sweep_at {
+            self.buckets.retain(|_, bucket| {
+                bucket.retain(|(k, i)| k.strong_count() > 0 && i.strong_count() > 0);
+                !bucket.is_empty()
+            });
+            self.sweep_at = (self.buckets.len() * 2).max(64);
+        }
+
+        (keys, index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema(keys: &[&str]) -> KeySchema {
+        KeySchema::new(keys.iter().map(|k| k.to_string()).collect()).unwrap()
+    }
+
+    #[test]
+    fn test_equal_key_orders_are_interned() {
+        let s1 = schema(&["a", "b", "c"]);
+        let s2 = schema(&["a", "b", "c"]);
+        assert!(Arc::ptr_eq(&s1.keys, &s2.keys));
+        assert!(Arc::ptr_eq(&s1.index, &s2.index));
+    }
+
+    #[test]
+    fn test_different_key_orders_are_distinct() {
+        let s1 = schema(&["x", "y"]);
+        let s2 = schema(&["y", "x"]);
+        assert!(!Arc::ptr_eq(&s1.index, &s2.index));
+        assert_eq!(s2.index.get("y"), Some(&0));
+    }
+
+    #[test]
+    fn test_dropped_schema_is_not_reused() {
+        let s1 = schema(&["only-once"]);
+        let weak = Arc::downgrade(&s1.keys);
+        drop(s1);
+        assert!(weak.upgrade().is_none());
+        let s2 = schema(&["only-once"]);
+        assert_eq!(s2.keys(), ["only-once"]);
+    }
+
+    #[test]
+    fn test_duplicate_keys_are_rejected() {
+        Python::initialize();
+        let keys = vec!["a".to_string(), "a".to_string()];
+        assert!(KeySchema::new(keys).is_err());
+    }
+}
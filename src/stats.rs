@@ -0,0 +1,277 @@
+//! Reduction kernels behind the statistical methods on `RedDict`.
+//!
+//! Callers filter out nulls (and NaN, when asked to) before values reach
+//! these functions, so any NaN seen here propagates into the result the same
+//! way it does in numpy.
+use pyo3::{exceptions::PyValueError, prelude::*};
+
+/// How a sequence of values is added up.
+#[derive(Clone, Copy)]
+pub(crate) enum Summation {
+    /// Left to right; the fastest, with error growing linearly in the length.
+    Naive,
+    /// Sums of blocks combined pairwise, as numpy does; error grows with the
+    /// logarithm of the length.
+    Pairwise,
+    /// Kahan–Neumaier compensated summation; accurate to about one rounding
+    /// regardless of length or cancellation.
+    Kahan,
+}
+
+impl Summation {
+    pub(crate) fn parse(method: &str) -> PyResult<Self> {
+        match method {
+            "naive" => Ok(Summation::Naive),
+            "pairwise" => Ok(Summation::Pairwise),
+            "kahan" => Ok(Summation::Kahan),
+            _ => Err(PyValueError::new_err(format!(
+                "method must be 'naive', 'pairwise' or 'kahan', got {method:?}"
+            ))),
+        }
+    }
+
+    pub(crate) fn sum(self, values: impl Iterator<Item = f64>) -> f64 {
+        match self {
+            Summation::Naive => values.sum(),
+            Summation::Pairwise => pairwise_sum(values),
+            Summation::Kahan => kahan_sum(values),
+        }
+    }
+}
+
+/// Number of values added naively before a block joins the pairwise tree.
+const PAIRWISE_BLOCK: usize = 128;
+
+fn pairwise_sum(values: impl Iterator<Item = f64>) -> f64 {
+    // Block sums are merged like a binary counter: `stack` holds one partial
+    // sum per level, each covering `2^level` blocks.
+    let mut stack: Vec<(u32, f64)> = Vec::new();
+    let mut push = |mut total: f64| {
+        let mut level = 0;
+        while let Some(&(top, partial)) = stack.last() {
+            if top != level {
+                break;
+            }
+            stack.pop();
+            total += partial;
+            level += 1;
+        }
+        stack.push((level, total));
+    };
+
+    let mut block = 0.0;
+    let mut len = 0;
+    for v in values {
+        block += v;
+        len += 1;
+        if len == PAIRWISE_BLOCK {
+            push(block);
+            block = 0.0;
+            len = 0;
+        }
+    }
+    stack
+        .iter()
+        .rev()
+        .fold(block, |total, &(_, partial)| total + partial)
+}
+
+fn kahan_sum(values: impl Iterator<Item = f64>) -> f64 {
+    let mut sum = 0.0_f64;
+    let mut compensation = 0.0;
+    for v in values {
+        let t = sum + v;
+        compensation += if sum.abs() >= v.abs() {
+            (sum - t) + v
+        } else {
+            (v - t) + sum
+        };
+        sum = t;
+    }
+    // Once the running sum overflows or meets an infinity the compensation
+    // is meaningless (inf - inf), so report the plain sum.
+    if sum.is_finite() {
+        sum + compensation
+    } else {
+        sum
+    }
+}
+
+/// Arithmetic mean, NaN when there are no values.
+pub(crate) fn mean(values: impl Iterator<Item = f64>, method: Summation) -> f64 {
+    let mut n = 0usize;
+    let total = method.sum(values.inspect(|_| n += 1));
+    if n == 0 {
+        f64::NAN
+    } else {
+        total / n as f64
+    }
+}
+
+/// Variance with `ddof` delta degrees of freedom, computed in one pass with
+/// Welford's algorithm. NaN when there are no more than `ddof` values.
+pub(crate) fn variance(values: impl Iterator<Item = f64>, ddof: usize) -> f64 {
+    let mut n = 0usize;
+    let mut mean = 0.0;
+    let mut m2 = 0.0;
+    for v in values {
+        n += 1;
+        let delta = v - mean;
+        mean += delta / n as f64;
+        m2 += delta * (v - mean);
+    }
+    if n <= ddof {
+        f64::NAN
+    } else {
+        m2 / (n - ddof) as f64
+    }
+}
+
+/// The first `(position, value)` for which no later value is `better`, or
+/// the first NaN if there is one. `None` when there are no values.
+pub(crate) fn arg_extreme<I, F>(values: I, better: F) -> Option<(usize, f64)>
+where
+    I: Iterator<Item = (usize, f64)>,
+    F: Fn(f64, f64) -> bool,
+{
+    let mut best: Option<(usize, f64)> = None;
+    for (i, v) in values {
+        if v.is_nan() {
+            return Some((i, v));
+        }
+        match best {
+            Some((_, b)) if !better(v, b) => {}
+            _ => best = Some((i, v)),
+        }
+    }
+    best
+}
+
+/// The `q`-th quantile with linear interpolation between the closest ranks
+/// (numpy's default). Reorders `values` in place; runs in linear time.
+pub(crate) fn quantile(values: &mut [f64], q: f64) -> f64 {
+    if values.is_empty() || values.iter().any(|v| v.is_nan()) {
+        return f64::NAN;
+    }
+    let pos = q * (values.len() - 1) as f64;
+    let lo = pos.floor() as usize;
+    let frac = pos - lo as f64;
+    let (_, lo_value, upper) = values.select_nth_unstable_by(lo, f64::total_cmp);
+    let lo_value = *lo_value;
+    if frac == 0.0 {
+        return lo_value;
+    }
+    let hi_value = upper.iter().copied().fold(f64::INFINITY, f64::min);
+    lo_value + (hi_value - lo_value) * frac
+}
+
+/// The `p`-norm: `sum(|v| ** p) ** (1 / p)`, with `p = inf` giving the
+/// largest magnitude and `p = 0` the number of non-zero values.
+pub(crate) fn norm(values: impl Iterator<Item = f64>, p: f64) -> f64 {
+    if p == f64::INFINITY {
+        let mut largest = 0.0_f64;
+        for v in values {
+            if v.is_nan() {
+                return f64::NAN;
+            }
+            largest = largest.max(v.abs());
+        }
+        largest
+    } else if p == 0.0 {
+        values.filter(|v| *v != 0.0).count() as f64
+    } else if p == 1.0 {
+        values.map(f64::abs).sum()
+    } else if p == 2.0 {
+        values.map(|v| v * v).sum::<f64>().sqrt()
+    } else {
+        values.map(|v| v.abs().powf(p)).sum::<f64>().powf(p.recip())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mean_and_variance() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(mean(values.into_iter(), Summation::Naive), 5.0);
+        assert_eq!(variance(values.into_iter(), 0), 4.0);
+        assert_eq!(variance(values.into_iter(), 1), 32.0 / 7.0);
+        assert!(mean(std::iter::empty(), Summation::Kahan).is_nan());
+        assert!(variance([1.0].into_iter(), 1).is_nan());
+    }
+
+    #[test]
+    fn test_variance_is_stable_for_large_offsets() {
+        let values = [1e9 + 4.0, 1e9 + 7.0, 1e9 + 13.0, 1e9 + 16.0];
+        assert_eq!(variance(values.into_iter(), 1), 30.0);
+    }
+
+    #[test]
+    fn test_arg_extreme_keeps_first_and_stops_at_nan() {
+        let values = [3.0, 1.0, 5.0, 1.0];
+        let min = arg_extreme(values.into_iter().enumerate(), |a, b| a < b);
+        assert_eq!(min, Some((1, 1.0)));
+        let max = arg_extreme(values.into_iter().enumerate(), |a, b| a > b);
+        assert_eq!(max, Some((2, 5.0)));
+        let with_nan = [3.0, f64::NAN, 0.0];
+        let (i, v) = arg_extreme(with_nan.into_iter().enumerate(), |a, b| a < b).unwrap();
+        assert!(i == 1 && v.is_nan());
+        assert_eq!(arg_extreme(std::iter::empty(), |a, b| a < b), None);
+    }
+
+    #[test]
+    fn test_quantile_interpolates_like_numpy() {
+        let mut values = [4.0, 1.0, 3.0, 2.0];
+        assert_eq!(quantile(&mut values, 0.5), 2.5);
+        assert_eq!(quantile(&mut values, 0.0), 1.0);
+        assert_eq!(quantile(&mut values, 1.0), 4.0);
+        assert_eq!(quantile(&mut values, 0.25), 1.75);
+        assert!(quantile(&mut [], 0.5).is_nan());
+        assert!(quantile(&mut [1.0, f64::NAN], 0.5).is_nan());
+    }
+
+    #[test]
+    fn test_norms() {
+        let values = [3.0, -4.0, 0.0];
+        assert_eq!(norm(values.into_iter(), 2.0), 5.0);
+        assert_eq!(norm(values.into_iter(), 1.0), 7.0);
+        assert_eq!(norm(values.into_iter(), f64::INFINITY), 4.0);
+        assert_eq!(norm(values.into_iter(), 0.0), 2.0);
+        assert!((norm(values.into_iter(), 3.0) - 91.0_f64.cbrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_summation_methods_agree_on_exact_sums() {
+        let values: Vec<f64> = (1..=1000).map(f64::from).collect();
+        for method in [Summation::Naive, Summation::Pairwise, Summation::Kahan] {
+            assert_eq!(method.sum(values.iter().copied()), 500500.0);
+            assert_eq!(method.sum(std::iter::empty()), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_kahan_recovers_cancelled_terms() {
+        let values = [1.0, 1e100, 1.0, -1e100];
+        assert_eq!(Summation::Naive.sum(values.into_iter()), 0.0);
+        assert_eq!(Summation::Kahan.sum(values.into_iter()), 2.0);
+    }
+
+    #[test]
+    fn test_pairwise_beats_naive_on_long_sums() {
+        let values = vec![0.1; 1_000_000];
+        let exact = 100_000.0;
+        let naive = Summation::Naive.sum(values.iter().copied());
+        let pairwise = Summation::Pairwise.sum(values.iter().copied());
+        assert!((pairwise - exact).abs() < (naive - exact).abs());
+    }
+
+    #[test]
+    fn test_kahan_keeps_infinities() {
+        let values = [1.0, f64::INFINITY, 1.0];
+        assert_eq!(Summation::Kahan.sum(values.into_iter()), f64::INFINITY);
+        let overflow = [f64::MAX, f64::MAX];
+        assert_eq!(Summation::Kahan.sum(overflow.into_iter()), f64::INFINITY);
+    }
+}
@@ -0,0 +1,144 @@
+//! numpy ufunc dispatch (`__array_ufunc__`).
+//!
+//! A ufunc called on RedDicts runs once over their value arrays and returns
+//! a RedDict on the layout of its first RedDict operand. A second RedDict is
+//! first aligned onto that layout exactly as the operators align it, with
+//! keys it lacks becoming nulls (`fill=None`); nulls in either operand stay
+//! null in the result. Boolean results (`np.isnan`, comparisons) become 1.0
+//! and 0.0.
+//!
+//! Operands are handed to the ufunc as `memoryview`s of the values, which
+//! numpy wraps without copying. An `out=` RedDict must hold the result's
+//! keys, in any order, and keeps its own order.
+use std::sync::Arc;
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyDict, PyMemoryView, PyTuple},
+};
+
+use crate::align::{align, Alignment};
+use crate::bitmap::Bitmap;
+use crate::{array, Operand, RedDict};
+
+/// Applies `ufunc` to `inputs`, or returns `NotImplemented` for the ufunc
+/// methods (`reduce`, `outer`, ...), multi-output ufuncs and operands
+/// other than RedDicts and numbers, so numpy reports them as unsupported.
+pub(crate) fn apply<'py>(
+    ufunc: &Bound<'py, PyAny>,
+    method: &str,
+    inputs: &Bound<'py, PyTuple>,
+    kwargs: Option<&Bound<'py, PyDict>>,
+) -> PyResult<Bound<'py, PyAny>> {
+    let py = ufunc.py();
+    let not_implemented = || Ok(py.NotImplemented().into_bound(py));
+    if method != "__call__" || ufunc.getattr("nout")?.extract::<usize>()? != 1 {
+        return not_implemented();
+    }
+
+    let kwargs = match kwargs {
+        Some(kwargs) => kwargs.copy()?,
+        None => PyDict::new(py),
+    };
+    let out = match kwargs.get_item("out")? {
+        Some(out) => {
+            kwargs.del_item("out")?;
+            let out = out.cast_into::<PyTuple>()?;
+            match out.get_item(0).ok().map(|o| o.cast_into::<RedDict>()) {
+                Some(Ok(target)) if out.len() == 1 => Some(target),
+                _ => return not_implemented(),
+            }
+        }
+        None => None,
+    };
+
+    let Ok(operands) = inputs
+        .iter()
+        .map(|input| input.extract::<Operand>())
+        .collect::<Result<Vec<_>, _>>()
+    else {
+        return not_implemented();
+    };
+    let view = |dict: &Bound<'py, RedDict>| PyMemoryView::from(dict.as_any());
+
+    let (layout, args) = match operands.as_slice() {
+        [Operand::Dict(d)] => (d.borrow().clone(), PyTuple::new(py, [view(d)?])?),
+        [Operand::Dict(a), Operand::Dict(b)] => {
+            // `aligned` holds b's values on a's layout, with the nulls of both.
+            let aligned = a
+                .borrow()
+                .merge_operator(py, &b.borrow(), None, |_, b| *b)?;
+            let args = (view(a)?, view(&Bound::new(py, aligned.clone())?)?).into_pyobject(py)?;
+            (aligned, args)
+        }
+        [Operand::Dict(a), Operand::Scalar(x)] => {
+            (a.borrow().clone(), (view(a)?, *x).into_pyobject(py)?)
+        }
+        [Operand::Scalar(x), Operand::Dict(b)] => {
+            (b.borrow().clone(), (*x, view(b)?).into_pyobject(py)?)
+        }
+        _ => return not_implemented(),
+    };
+
+    // A ufunc result has no nulls of its own; the layout's are applied below.
+    let (values, _) = array::extract_values(&ufunc.call(&args, Some(&kwargs))?)?;
+    if values.len() != layout.keys.len() {
+        return Err(PyValueError::new_err(format!(
+            "ufunc returned {} values for a RedDict of length {}",
+            values.len(),
+            layout.keys.len()
+        )));
+    }
+
+    let mut new = RedDict {
+        values: Arc::new(values),
+        ..layout
+    };
+    new.mask_nulls();
+
+    match out {
+        Some(target) => {
+            {
+                let mut target = target.try_borrow_mut()?;
+                target.check_mutable()?;
+                let new = onto_layout(new, &target)?;
+                *target = RedDict {
+                    frozen: false,
+                    ..new
+                };
+            }
+            Ok(target.into_any())
+        }
+        None => Ok(Bound::new(py, new)?.into_any()),
+    }
+}
+
+/// Moves `new` onto the layout of the `out=` RedDict `target`, which must
+/// hold the same keys. Raises `ValueError` otherwise, rather than replacing
+/// `target`'s keys.
+fn onto_layout(new: RedDict, target: &RedDict) -> PyResult<RedDict> {
+    let order = match align(&target.index, &new.index) {
+        Alignment::Identical => return Ok(new),
+        Alignment::Gather(order) => order,
+    };
+    let order: Option<Vec<usize>> = order.iter().copied().collect();
+    let order = match order {
+        Some(order) if order.len() == new.keys.len() => order,
+        _ => {
+            return Err(PyValueError::new_err(
+                "out= must be a RedDict with the same keys as the result",
+            ))
+        }
+    };
+    let values = order.iter().map(|&j| new.values[j]).collect();
+    let validity = Bitmap::from_fn(order.len(), |i| new.value_at(order[i]).is_some());
+    Ok(RedDict {
+        keys: Arc::clone(&target.keys),
+        index: Arc::clone(&target.index),
+        values: Arc::new(values),
+        validity: None,
+        frozen: false,
+    }
+    .with_validity(validity))
+}